@@ -0,0 +1,10 @@
+ALTER TABLE photos DROP COLUMN lens_id;
+ALTER TABLE photos DROP COLUMN exposure_time;
+ALTER TABLE photos DROP COLUMN aperture;
+ALTER TABLE photos DROP COLUMN iso;
+ALTER TABLE photos DROP COLUMN focal_length;
+ALTER TABLE photos DROP COLUMN focal_length_35;
+ALTER TABLE photos DROP COLUMN flash;
+ALTER TABLE photos DROP COLUMN altitude;
+ALTER TABLE photos DROP COLUMN direction;
+DROP TABLE lenses;
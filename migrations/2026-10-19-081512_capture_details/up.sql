@@ -0,0 +1,20 @@
+-- Lens models are stored like cameras, since the same lens may be
+-- used on several cameras.
+CREATE TABLE lenses (
+  id SERIAL PRIMARY KEY,
+  model VARCHAR UNIQUE NOT NULL
+);
+
+-- Capture details from exif.  All nullable, as most old photos (and
+-- all scanned ones) lack some or all of them.
+-- exposure_time is in seconds, focal lengths in mm, altitude in
+-- meters above sea level and direction in degrees.
+ALTER TABLE photos ADD COLUMN lens_id INTEGER REFERENCES lenses (id);
+ALTER TABLE photos ADD COLUMN exposure_time REAL;
+ALTER TABLE photos ADD COLUMN aperture REAL;
+ALTER TABLE photos ADD COLUMN iso INTEGER;
+ALTER TABLE photos ADD COLUMN focal_length REAL;
+ALTER TABLE photos ADD COLUMN focal_length_35 SMALLINT;
+ALTER TABLE photos ADD COLUMN flash BOOLEAN;
+ALTER TABLE photos ADD COLUMN altitude REAL;
+ALTER TABLE photos ADD COLUMN direction REAL;
@@ -0,0 +1,6 @@
+DROP INDEX photos_camera_idx;
+DROP INDEX photos_lens_idx;
+DROP INDEX photos_iso_idx;
+DROP INDEX photos_focal_length_idx;
+ALTER TABLE cameras DROP COLUMN slug;
+ALTER TABLE lenses DROP COLUMN slug;
@@ -0,0 +1,23 @@
+-- Slugs for cameras and lenses, to make them usable in search.
+-- The slug is based on the model only, with the id appended for the
+-- (unlikely) case of several models giving the same slug.
+ALTER TABLE cameras ADD COLUMN slug VARCHAR;
+UPDATE cameras SET slug = trim(both '-' from
+  regexp_replace(lower(model), '[^a-z0-9]+', '-', 'g'));
+UPDATE cameras c SET slug = slug || '-' || id
+  WHERE EXISTS (SELECT 1 FROM cameras o WHERE o.slug = c.slug AND o.id < c.id);
+ALTER TABLE cameras ALTER COLUMN slug SET NOT NULL;
+CREATE UNIQUE INDEX cameras_slug_idx ON cameras (slug);
+
+ALTER TABLE lenses ADD COLUMN slug VARCHAR;
+UPDATE lenses SET slug = trim(both '-' from
+  regexp_replace(lower(model), '[^a-z0-9]+', '-', 'g'));
+UPDATE lenses l SET slug = slug || '-' || id
+  WHERE EXISTS (SELECT 1 FROM lenses o WHERE o.slug = l.slug AND o.id < l.id);
+ALTER TABLE lenses ALTER COLUMN slug SET NOT NULL;
+CREATE UNIQUE INDEX lenses_slug_idx ON lenses (slug);
+
+CREATE INDEX photos_camera_idx ON photos (camera_id);
+CREATE INDEX photos_lens_idx ON photos (lens_id);
+CREATE INDEX photos_iso_idx ON photos (iso);
+CREATE INDEX photos_focal_length_idx ON photos (focal_length);
@@ -0,0 +1,2 @@
+DROP INDEX photos_instant_idx;
+ALTER TABLE photos DROP COLUMN utc_date;
@@ -0,0 +1,8 @@
+-- The date column is the local (wall-clock) time where the photo was
+-- taken.  This is the actual instant, when the time zone is known.
+ALTER TABLE photos ADD COLUMN utc_date TIMESTAMP WITH TIME ZONE;
+
+-- Photos are ordered by the instant when known, otherwise by the
+-- local time as if it was utc.
+CREATE INDEX photos_instant_idx
+  ON photos (coalesce(utc_date, date AT TIME ZONE 'UTC') DESC NULLS LAST);
@@ -0,0 +1,2 @@
+DROP TABLE clock_shift_photos;
+DROP TABLE clock_shifts;
@@ -0,0 +1,22 @@
+-- Corrections of camera clocks.  A shift moves the date of all
+-- photos from a camera in a time window (in the uncorrected time).
+CREATE TABLE clock_shifts (
+  id SERIAL PRIMARY KEY,
+  camera_id INTEGER NOT NULL REFERENCES cameras (id),
+  since TIMESTAMP NOT NULL,
+  until TIMESTAMP NOT NULL,
+  seconds INTEGER NOT NULL,
+  created TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- The original dates of the shifted photos, so a shift can be undone.
+CREATE TABLE clock_shift_photos (
+  id SERIAL PRIMARY KEY,
+  shift_id INTEGER NOT NULL REFERENCES clock_shifts (id),
+  photo_id INTEGER NOT NULL REFERENCES photos (id),
+  old_date TIMESTAMP,
+  old_utc_date TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX clock_shift_photos_shift_idx ON clock_shift_photos (shift_id);
+CREATE INDEX clock_shift_photos_photo_idx ON clock_shift_photos (photo_id);
@@ -0,0 +1,3 @@
+-- True if the date of a photo is set by hand, and should not be
+-- overwritten by a date found in exif.
+ALTER TABLE photos ADD COLUMN date_manual BOOLEAN NOT NULL DEFAULT false;
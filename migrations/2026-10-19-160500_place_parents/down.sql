@@ -0,0 +1,2 @@
+DROP INDEX places_parent_idx;
+ALTER TABLE places DROP COLUMN parent_id;
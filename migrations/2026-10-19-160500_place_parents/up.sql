@@ -0,0 +1,4 @@
+-- The closest larger place containing a place, as derived from the
+-- is_in results from overpass.
+ALTER TABLE places ADD COLUMN parent_id INTEGER REFERENCES places (id);
+CREATE INDEX places_parent_idx ON places (parent_id);
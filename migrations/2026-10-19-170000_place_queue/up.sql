@@ -0,0 +1,11 @@
+-- Photos waiting for places to be fetched.  When a lookup found no
+-- places for a photo, the time of that is stored in no_places, and
+-- the photo is kept in the queue so it is not queued again until its
+-- position is changed.
+CREATE TABLE place_queue (
+  photo_id INTEGER PRIMARY KEY REFERENCES photos (id),
+  added TIMESTAMP NOT NULL DEFAULT now(),
+  attempts SMALLINT NOT NULL DEFAULT 0,
+  last_error VARCHAR,
+  no_places TIMESTAMP
+);
@@ -0,0 +1,2 @@
+DROP TABLE place_names;
+ALTER TABLE places DROP COLUMN osm_tags;
@@ -0,0 +1,12 @@
+-- The osm tags of a place (as json), to make it possible to classify
+-- places again when the rules change.
+ALTER TABLE places ADD COLUMN osm_tags TEXT;
+
+-- Names of places in the preferred languages.
+CREATE TABLE place_names (
+  id SERIAL PRIMARY KEY,
+  place_id INTEGER NOT NULL REFERENCES places (id),
+  lang VARCHAR NOT NULL,
+  name VARCHAR NOT NULL,
+  UNIQUE (place_id, lang)
+);
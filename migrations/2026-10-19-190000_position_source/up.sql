@@ -0,0 +1,3 @@
+-- Where a position comes from: 'exif', 'manual' or 'gpx' (null for
+-- positions stored before this was tracked).
+ALTER TABLE positions ADD COLUMN source VARCHAR;
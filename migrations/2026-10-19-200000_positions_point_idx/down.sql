@@ -0,0 +1 @@
+DROP INDEX positions_point_idx;
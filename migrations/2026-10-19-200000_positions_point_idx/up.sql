@@ -0,0 +1,2 @@
+-- A spatial index for searching photos in an area.
+CREATE INDEX positions_point_idx ON positions USING gist (point(latitude, longitude));
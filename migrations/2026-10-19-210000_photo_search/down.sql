@@ -0,0 +1,13 @@
+DROP TRIGGER cameras_search ON cameras;
+DROP TRIGGER places_search ON places;
+DROP TRIGGER people_search ON people;
+DROP TRIGGER tags_search ON tags;
+DROP FUNCTION name_search_trigger();
+DROP TRIGGER photo_places_search ON photo_places;
+DROP TRIGGER photo_people_search ON photo_people;
+DROP TRIGGER photo_tags_search ON photo_tags;
+DROP FUNCTION photo_link_search_trigger();
+DROP TRIGGER photos_search ON photos;
+DROP FUNCTION photos_search_trigger();
+DROP FUNCTION photo_search_doc(INTEGER, VARCHAR, INTEGER);
+ALTER TABLE photos DROP COLUMN search_doc;
@@ -0,0 +1,82 @@
+-- Full-text search of photos.
+--
+-- The search document of a photo is the names of its tags, people,
+-- places and camera, and the components of its path.  It is kept up
+-- to date by triggers, so it is not in the diesel schema.
+ALTER TABLE photos ADD COLUMN search_doc TSVECTOR;
+
+CREATE FUNCTION photo_search_doc(photo INTEGER, path VARCHAR, camera INTEGER)
+RETURNS TSVECTOR LANGUAGE SQL STABLE AS $$
+  SELECT
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(t.tag_name, ' ') FROM tags t
+        JOIN photo_tags pt ON pt.tag_id = t.id WHERE pt.photo_id = photo
+    ), '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(h.person_name, ' ') FROM people h
+        JOIN photo_people pp ON pp.person_id = h.id WHERE pp.photo_id = photo
+    ), '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(l.place_name, ' ') FROM places l
+        JOIN photo_places pl ON pl.place_id = l.id WHERE pl.photo_id = photo
+    ), '') || ' ' || coalesce((
+      SELECT string_agg(n.name, ' ') FROM place_names n
+        JOIN photo_places pl ON pl.place_id = n.place_id WHERE pl.photo_id = photo
+    ), '')), 'B') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT manufacturer || ' ' || model FROM cameras WHERE id = camera
+    ), '')), 'C') ||
+    setweight(to_tsvector('simple',
+      regexp_replace(path, '[/_.-]+', ' ', 'g')), 'D')
+$$;
+
+CREATE FUNCTION photos_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  NEW.search_doc := photo_search_doc(NEW.id, NEW.path, NEW.camera_id);
+  RETURN NEW;
+END
+$$;
+
+CREATE TRIGGER photos_search BEFORE INSERT OR UPDATE OF path, camera_id
+  ON photos FOR EACH ROW EXECUTE PROCEDURE photos_search_trigger();
+
+-- Refresh the search document of the photo of a changed link row.
+CREATE FUNCTION photo_link_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  UPDATE photos SET search_doc = photo_search_doc(id, path, camera_id)
+    WHERE id = (CASE WHEN TG_OP = 'DELETE' THEN OLD.photo_id ELSE NEW.photo_id END);
+  RETURN NULL;
+END
+$$;
+
+CREATE TRIGGER photo_tags_search AFTER INSERT OR UPDATE OR DELETE
+  ON photo_tags FOR EACH ROW EXECUTE PROCEDURE photo_link_search_trigger();
+CREATE TRIGGER photo_people_search AFTER INSERT OR UPDATE OR DELETE
+  ON photo_people FOR EACH ROW EXECUTE PROCEDURE photo_link_search_trigger();
+CREATE TRIGGER photo_places_search AFTER INSERT OR UPDATE OR DELETE
+  ON photo_places FOR EACH ROW EXECUTE PROCEDURE photo_link_search_trigger();
+
+-- Renaming is rare, so just refresh all photos of a renamed item.
+CREATE FUNCTION name_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  UPDATE photos SET search_doc = photo_search_doc(id, path, camera_id)
+    WHERE id IN (
+      SELECT photo_id FROM photo_tags WHERE TG_TABLE_NAME = 'tags' AND tag_id = NEW.id
+      UNION SELECT photo_id FROM photo_people WHERE TG_TABLE_NAME = 'people' AND person_id = NEW.id
+      UNION SELECT photo_id FROM photo_places WHERE TG_TABLE_NAME = 'places' AND place_id = NEW.id
+      UNION SELECT id FROM photos WHERE TG_TABLE_NAME = 'cameras' AND camera_id = NEW.id);
+  RETURN NULL;
+END
+$$;
+
+CREATE TRIGGER tags_search AFTER UPDATE OF tag_name
+  ON tags FOR EACH ROW EXECUTE PROCEDURE name_search_trigger();
+CREATE TRIGGER people_search AFTER UPDATE OF person_name
+  ON people FOR EACH ROW EXECUTE PROCEDURE name_search_trigger();
+CREATE TRIGGER places_search AFTER UPDATE OF place_name
+  ON places FOR EACH ROW EXECUTE PROCEDURE name_search_trigger();
+CREATE TRIGGER cameras_search AFTER UPDATE OF manufacturer, model
+  ON cameras FOR EACH ROW EXECUTE PROCEDURE name_search_trigger();
+
+UPDATE photos SET search_doc = photo_search_doc(id, path, camera_id);
+CREATE INDEX photos_search_idx ON photos USING gin (search_doc);
@@ -0,0 +1,11 @@
+-- Named searches, stored as the query string of the search page.
+-- The photos are found again each time the search is shown, so
+-- newly tagged photos are included.
+CREATE TABLE saved_searches (
+  id SERIAL PRIMARY KEY,
+  slug VARCHAR UNIQUE NOT NULL,
+  name VARCHAR NOT NULL,
+  query VARCHAR NOT NULL,
+  is_public BOOLEAN NOT NULL DEFAULT false,
+  created TIMESTAMP NOT NULL DEFAULT now()
+);
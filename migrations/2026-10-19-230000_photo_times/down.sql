@@ -0,0 +1,3 @@
+DROP TRIGGER photos_updated ON photos;
+DROP FUNCTION photos_updated_trigger();
+ALTER TABLE photos DROP COLUMN created, DROP COLUMN updated;
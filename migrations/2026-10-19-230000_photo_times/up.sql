@@ -0,0 +1,21 @@
+-- When a photo was added to (and last changed in) the database.
+-- Photos that were already there are considered added now.
+ALTER TABLE photos
+  ADD COLUMN created TIMESTAMP NOT NULL DEFAULT now(),
+  ADD COLUMN updated TIMESTAMP NOT NULL DEFAULT now();
+
+-- Tagging a photo updates its search_doc, so it counts as a change.
+CREATE FUNCTION photos_updated_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  IF NEW IS DISTINCT FROM OLD THEN
+    NEW.updated := now();
+  END IF;
+  RETURN NEW;
+END
+$$;
+
+CREATE TRIGGER photos_updated BEFORE UPDATE
+  ON photos FOR EACH ROW EXECUTE PROCEDURE photos_updated_trigger();
+
+CREATE INDEX photos_created_idx ON photos (created);
+CREATE INDEX photos_updated_idx ON photos (updated);
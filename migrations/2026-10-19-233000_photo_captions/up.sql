@@ -0,0 +1,70 @@
+-- A free-text title and description of a photo.  The caption is
+-- manual if it is set by hand, and should not be overwritten by a
+-- caption found in the image file, even if it is cleared.
+ALTER TABLE photos ADD COLUMN title VARCHAR, ADD COLUMN description TEXT,
+  ADD COLUMN caption_manual BOOLEAN NOT NULL DEFAULT false;
+
+-- Include the title and description in the search document.
+DROP FUNCTION photo_search_doc(INTEGER, VARCHAR, INTEGER);
+CREATE FUNCTION photo_search_doc(
+  photo INTEGER, path VARCHAR, camera INTEGER, title VARCHAR, description TEXT
+) RETURNS TSVECTOR LANGUAGE SQL STABLE AS $$
+  SELECT
+    setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(t.tag_name, ' ') FROM tags t
+        JOIN photo_tags pt ON pt.tag_id = t.id WHERE pt.photo_id = photo
+    ), '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(h.person_name, ' ') FROM people h
+        JOIN photo_people pp ON pp.person_id = h.id WHERE pp.photo_id = photo
+    ), '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(description, '')), 'B') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT string_agg(l.place_name, ' ') FROM places l
+        JOIN photo_places pl ON pl.place_id = l.id WHERE pl.photo_id = photo
+    ), '') || ' ' || coalesce((
+      SELECT string_agg(n.name, ' ') FROM place_names n
+        JOIN photo_places pl ON pl.place_id = n.place_id WHERE pl.photo_id = photo
+    ), '')), 'B') ||
+    setweight(to_tsvector('simple', coalesce((
+      SELECT manufacturer || ' ' || model FROM cameras WHERE id = camera
+    ), '')), 'C') ||
+    setweight(to_tsvector('simple',
+      regexp_replace(path, '[/_.-]+', ' ', 'g')), 'D')
+$$;
+
+CREATE OR REPLACE FUNCTION photos_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  NEW.search_doc := photo_search_doc(
+    NEW.id, NEW.path, NEW.camera_id, NEW.title, NEW.description);
+  RETURN NEW;
+END
+$$;
+
+DROP TRIGGER photos_search ON photos;
+CREATE TRIGGER photos_search
+  BEFORE INSERT OR UPDATE OF path, camera_id, title, description
+  ON photos FOR EACH ROW EXECUTE PROCEDURE photos_search_trigger();
+
+CREATE OR REPLACE FUNCTION photo_link_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  UPDATE photos
+    SET search_doc = photo_search_doc(id, path, camera_id, title, description)
+    WHERE id = (CASE WHEN TG_OP = 'DELETE' THEN OLD.photo_id ELSE NEW.photo_id END);
+  RETURN NULL;
+END
+$$;
+
+CREATE OR REPLACE FUNCTION name_search_trigger() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+  UPDATE photos
+    SET search_doc = photo_search_doc(id, path, camera_id, title, description)
+    WHERE id IN (
+      SELECT photo_id FROM photo_tags WHERE TG_TABLE_NAME = 'tags' AND tag_id = NEW.id
+      UNION SELECT photo_id FROM photo_people WHERE TG_TABLE_NAME = 'people' AND person_id = NEW.id
+      UNION SELECT photo_id FROM photo_places WHERE TG_TABLE_NAME = 'places' AND place_id = NEW.id
+      UNION SELECT id FROM photos WHERE TG_TABLE_NAME = 'cameras' AND camera_id = NEW.id);
+  RETURN NULL;
+END
+$$;
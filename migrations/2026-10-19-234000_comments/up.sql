@@ -0,0 +1,18 @@
+-- Comments on photos, and photos marked as favourites, by users.
+CREATE TABLE comments (
+  id SERIAL PRIMARY KEY,
+  photo_id INTEGER NOT NULL REFERENCES photos (id) ON DELETE CASCADE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  body TEXT NOT NULL,
+  created TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX comments_photo_idx ON comments (photo_id);
+CREATE INDEX comments_created_idx ON comments (created);
+
+CREATE TABLE favourites (
+  photo_id INTEGER NOT NULL REFERENCES photos (id) ON DELETE CASCADE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  created TIMESTAMP NOT NULL DEFAULT now(),
+  PRIMARY KEY (photo_id, user_id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN own_ratings;
+DROP TABLE ratings;
@@ -0,0 +1,12 @@
+-- Personal ratings of photos, 0 - 100 like photos.grade.
+CREATE TABLE ratings (
+  photo_id INTEGER NOT NULL REFERENCES photos (id) ON DELETE CASCADE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  grade SMALLINT NOT NULL CHECK (grade BETWEEN 0 AND 100),
+  updated TIMESTAMP NOT NULL DEFAULT now(),
+  PRIMARY KEY (photo_id, user_id)
+);
+
+-- Let a user see groups of photos represented by the photos they
+-- rated highest, rather than by the global grade.
+ALTER TABLE users ADD COLUMN own_ratings BOOLEAN NOT NULL DEFAULT false;
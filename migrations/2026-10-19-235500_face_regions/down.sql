@@ -0,0 +1,6 @@
+ALTER TABLE photo_people
+  DROP CONSTRAINT photo_people_region,
+  DROP COLUMN region_x,
+  DROP COLUMN region_y,
+  DROP COLUMN region_w,
+  DROP COLUMN region_h;
@@ -0,0 +1,12 @@
+-- Where in a photo a person is, as fractions of the width and height
+-- of the photo as shown (after rotation).  All null if unknown.
+ALTER TABLE photo_people
+  ADD COLUMN region_x REAL,
+  ADD COLUMN region_y REAL,
+  ADD COLUMN region_w REAL,
+  ADD COLUMN region_h REAL,
+  ADD CONSTRAINT photo_people_region CHECK (
+    (region_x IS NULL AND region_y IS NULL AND region_w IS NULL AND region_h IS NULL)
+    OR (region_x BETWEEN 0 AND 1 AND region_y BETWEEN 0 AND 1
+        AND region_w > 0 AND region_h > 0
+        AND region_x + region_w <= 1.0001 AND region_y + region_h <= 1.0001));
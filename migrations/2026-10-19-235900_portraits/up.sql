@@ -0,0 +1,15 @@
+-- A chosen photo, and an area of it, to represent a person.
+CREATE TABLE portraits (
+  person_id INTEGER PRIMARY KEY REFERENCES people (id) ON DELETE CASCADE,
+  photo_id INTEGER NOT NULL REFERENCES photos (id) ON DELETE CASCADE,
+  region_x REAL NOT NULL,
+  region_y REAL NOT NULL,
+  region_w REAL NOT NULL,
+  region_h REAL NOT NULL,
+  CONSTRAINT portraits_region CHECK (
+    region_x >= 0 AND region_y >= 0 AND region_w > 0 AND region_h > 0
+    AND region_x + region_w <= 1.0001 AND region_y + region_h <= 1.0001
+  )
+);
+
+CREATE INDEX portraits_photo_idx ON portraits (photo_id);
@@ -203,6 +203,259 @@
         i.focus();
     }
 
+    function caption_form(event) {
+        event.target.disabled = true;
+        var imgid = details.dataset.imgid;
+        var description = meta.querySelector('.description');
+        var f = d.createElement("form");
+        f.className = "admin caption";
+        f.action = "/adm/caption";
+        f.method = "post";
+        var l = d.createElement("label");
+        l.innerHTML = event.target.title;
+        f.appendChild(l);
+        var i = d.createElement("input");
+        i.type="hidden";
+        i.name="image";
+        i.value = imgid;
+        f.appendChild(i);
+        i = d.createElement("input");
+        i.name="title";
+        i.placeholder="Title";
+        i.value = details.dataset.title || '';
+        f.appendChild(i);
+        let t = d.createElement("textarea");
+        t.name="description";
+        t.placeholder="Description";
+        t.value = description ? description.textContent : '';
+        f.appendChild(t);
+        let s = d.createElement("button");
+        s.innerHTML = "Ok";
+        s.type = "submit";
+        f.appendChild(s);
+        let c = d.createElement("button");
+        c.innerHTML = "&#x1f5d9;";
+        c.className = 'close';
+        c.title = 'close';
+        c.onclick = e => {
+            e.target.closest('form').remove();
+            event.target.disabled = false; // The old event creating this form
+            event.target.focus();
+        };
+        f.appendChild(c);
+        f.addEventListener('keydown', e => {
+            if (e.code === 'Escape') {
+                e.target.closest('form').remove();
+                event.target.disabled = false;
+                event.target.focus();
+                e.preventDefault();
+                e.stopPropagation();
+                return false;
+            }
+            return true;
+        });
+        meta.insertBefore(f, meta.querySelector('#map'));
+        i.focus();
+    }
+
+    function region_form(event) {
+        event.target.disabled = true;
+        var imgid = details.dataset.imgid;
+        var item = details.querySelector('.item');
+        var f = d.createElement("form");
+        f.className = "admin region";
+        f.action = "/adm/region";
+        f.method = "post";
+        var l = d.createElement("label");
+        l.innerHTML = "Drag on the photo to mark the face of";
+        f.appendChild(l);
+        var i = d.createElement("input");
+        i.type="hidden";
+        i.name="image";
+        i.value = imgid;
+        f.appendChild(i);
+        let area = {};
+        ['x', 'y', 'w', 'h'].forEach(n => {
+            area[n] = d.createElement("input");
+            area[n].type = "hidden";
+            area[n].name = n;
+            f.appendChild(area[n]);
+        });
+        i = d.createElement("input");
+        i.name = "person";
+        i.id = "regionperson";
+        i.required = true;
+        i.autocomplete = "off";
+        i.setAttribute('list', 'regionpeople');
+        l.htmlFor = i.id;
+        f.appendChild(i);
+        let names = d.createElement("datalist");
+        names.id = 'regionpeople';
+        meta.querySelectorAll('.people a').forEach(a => {
+            let o = d.createElement("option");
+            o.value = a.textContent;
+            names.appendChild(o);
+        });
+        f.appendChild(names);
+        let s = d.createElement("button");
+        s.innerHTML = "Ok";
+        s.type = "submit";
+        s.disabled = true;
+        f.appendChild(s);
+        let clear = d.createElement("button");
+        clear.innerHTML = "Clear";
+        clear.type = "submit";
+        clear.name = "clear";
+        clear.value = "t";
+        clear.title = "Forget where this person is in the photo";
+        f.appendChild(clear);
+        let c = d.createElement("button");
+        c.innerHTML = "&#x1f5d9;";
+        c.className = 'close';
+        c.title = 'close';
+        f.appendChild(c);
+
+        let box = d.createElement("div");
+        box.className = "region edit";
+        let start = null;
+        function pos(e) {
+            let r = item.getBoundingClientRect();
+            return [
+                Math.min(Math.max((e.clientX - r.left) / r.width, 0), 1),
+                Math.min(Math.max((e.clientY - r.top) / r.height, 0), 1)
+            ];
+        }
+        function draw(e) {
+            let [x1, y1] = pos(e);
+            let [x0, y0] = start;
+            let x = Math.min(x0, x1), y = Math.min(y0, y1);
+            let w = Math.abs(x1 - x0), h = Math.abs(y1 - y0);
+            box.style.left = 100 * x + '%';
+            box.style.top = 100 * y + '%';
+            box.style.width = 100 * w + '%';
+            box.style.height = 100 * h + '%';
+            area.x.value = x.toFixed(4);
+            area.y.value = y.toFixed(4);
+            area.w.value = w.toFixed(4);
+            area.h.value = h.toFixed(4);
+            s.disabled = !(w > 0 && h > 0);
+        }
+        function down(e) {
+            start = pos(e);
+            item.appendChild(box);
+            draw(e);
+            e.preventDefault();
+        }
+        function move(e) {
+            if (start) {
+                draw(e);
+                e.preventDefault();
+            }
+        }
+        function up(e) {
+            if (start) {
+                draw(e);
+                start = null;
+            }
+        }
+        // Drawing should not toggle the zoom of the image.
+        function noclick(e) {
+            e.stopPropagation();
+        }
+        item.addEventListener('pointerdown', down);
+        item.addEventListener('pointermove', move);
+        item.addEventListener('pointerup', up);
+        item.addEventListener('click', noclick, true);
+        item.classList.add('drawing');
+        function close() {
+            item.removeEventListener('pointerdown', down);
+            item.removeEventListener('pointermove', move);
+            item.removeEventListener('pointerup', up);
+            item.removeEventListener('click', noclick, true);
+            item.classList.remove('drawing');
+            box.remove();
+            f.remove();
+            event.target.disabled = false; // The old event creating this form
+            event.target.focus();
+        }
+        c.onclick = e => { close(); e.preventDefault(); };
+        f.addEventListener('keydown', e => {
+            if (e.code === 'Escape') {
+                close();
+                e.preventDefault();
+                e.stopPropagation();
+                return false;
+            }
+            return true;
+        });
+        meta.insertBefore(f, meta.querySelector('#map'));
+        i.focus();
+    }
+
+    function date_form(event) {
+        event.target.disabled = true;
+        var imgid = details.dataset.imgid;
+        var f = d.createElement("form");
+        f.className = "admin date";
+        f.action = "/adm/date";
+        f.method = "post";
+        var l = d.createElement("label");
+        l.innerHTML = event.target.title;
+        f.appendChild(l);
+        var i = d.createElement("input");
+        i.type="hidden";
+        i.name="image";
+        i.value = imgid;
+        f.appendChild(i);
+        i = d.createElement("input");
+        i.type="datetime-local";
+        i.step=1;
+        i.name="date";
+        i.id="datevalue";
+        l.htmlFor = i.id;
+        if (details.dataset.date) {
+            i.value=details.dataset.date;
+        }
+        f.appendChild(i);
+        let s = d.createElement("button");
+        s.innerHTML = "Ok";
+        s.type = "submit";
+        f.appendChild(s);
+        [["prev", "\u21e4", "Copy date from previous dated photo"],
+         ["next", "\u21e5", "Copy date from next dated photo"]].map(x => {
+            let b = d.createElement("button");
+            b.type = "submit";
+            b.name = "copy";
+            b.value = x[0];
+            b.innerHTML = x[1];
+            b.title = x[2];
+            f.appendChild(b);
+        });
+        let c = d.createElement("button");
+        c.innerHTML = "&#x1f5d9;";
+        c.className = 'close';
+        c.title = 'close';
+        c.onclick = e => {
+            e.target.closest('form').remove();
+            event.target.disabled = false; // The old event creating this form
+            event.target.focus();
+        };
+        f.appendChild(c);
+        f.addEventListener('keydown', e => {
+            if (e.code === 'Escape') {
+                e.target.closest('form').remove();
+                event.target.disabled = false;
+                event.target.focus();
+                e.preventDefault();
+                e.stopPropagation();
+                return false;
+            }
+            return true;
+        });
+        meta.insertBefore(f, meta.querySelector('#map'));
+        i.focus();
+    }
+
     function location_form(event) {
         event.target.disabled = true;
         var imgid = details.dataset.imgid;
@@ -355,6 +608,29 @@
         r.title = "Grade";
         r.accessKey = "g";
         p.appendChild(r);
+
+        p.appendChild(d.createTextNode(" "));
+        r = d.createElement("button");
+        r.onclick = e => date_form(e);
+        r.innerHTML = "\u{1f4c5}";
+        r.title = "Date";
+        p.appendChild(r);
+
+        p.appendChild(d.createTextNode(" "));
+        r = d.createElement("button");
+        r.onclick = e => caption_form(e);
+        r.innerHTML = "\u270e";
+        r.title = "Caption";
+        r.accessKey = "c";
+        p.appendChild(r);
+
+        p.appendChild(d.createTextNode(" "));
+        r = d.createElement("button");
+        r.onclick = e => region_form(e);
+        r.innerHTML = "\u2b1a";
+        r.title = "Mark face";
+        r.accessKey = "f";
+        p.appendChild(r);
         meta.appendChild(p);
     }
 })(document)
@@ -0,0 +1,119 @@
+(function(d) {
+  let show = d.querySelector('div.slideshow');
+  let controls = d.querySelector('form.controls');
+  let link = show.querySelector('a.photo');
+  let img = link.querySelector('img');
+  let caption = show.querySelector('.caption');
+  let pause = controls.querySelector('.pause');
+  let size = show.dataset.size;
+  let interval = 1000 * show.dataset.interval;
+  let photos = [], pos = -1, timer = null, paused = false;
+  let next = show.dataset.src, loading = null;
+
+  // Get the next window of photo ids from the listing.
+  function load() {
+    if (!loading && next) {
+      loading = fetch(next)
+        .then(r => r.json())
+        .then(w => {
+          photos = photos.concat(w.photos);
+          next = w.next ? w.next + '&json=true' : null;
+          loading = null;
+        })
+        .catch(() => { next = null; loading = null; });
+    }
+    return loading || Promise.resolve();
+  }
+  function src(id) {
+    return `/img/${id}-${size}.jpg`;
+  }
+  function schedule() {
+    clearTimeout(timer);
+    if (!paused) {
+      timer = setTimeout(() => go(1), interval);
+    }
+  }
+  function go(step) {
+    if (photos.length == 0) {
+      return;
+    }
+    if (pos + step >= photos.length && next) {
+      load().then(() => go(step));
+      return;
+    }
+    pos = (pos + step + photos.length) % photos.length;
+    let photo = photos[pos];
+    img.src = src(photo.id);
+    img.alt = photo.title || `Photo ${photo.id}`;
+    link.href = `/img/${photo.id}`;
+    caption.querySelector('h2').textContent = photo.title || '';
+    caption.querySelector('p').textContent = photo.description || '';
+    caption.hidden = !(photo.title || photo.description);
+    // Preload the next photo, and the window after it when close.
+    if (pos + 1 < photos.length) {
+      new Image().src = src(photos[pos + 1].id);
+    }
+    if (pos + 2 >= photos.length) {
+      load();
+    }
+    schedule();
+  }
+  function toggle() {
+    paused = !paused;
+    pause.textContent = paused ? 'Play' : 'Pause';
+    schedule();
+  }
+
+  controls.querySelector('.prev').addEventListener('click', () => go(-1));
+  controls.querySelector('.next').addEventListener('click', () => go(1));
+  pause.addEventListener('click', toggle);
+  d.addEventListener('keydown', e => {
+    if (e.target.tagName == 'INPUT') {
+      return;
+    }
+    switch(e.key) {
+    case 'ArrowRight':
+    case 'PageDown':
+      go(1);
+      break;
+    case 'ArrowLeft':
+    case 'PageUp':
+      go(-1);
+      break;
+    case ' ':
+      toggle();
+      break;
+    case 'f':
+      if (d.fullscreenElement) {
+        d.exitFullscreen();
+      } else {
+        d.documentElement.requestFullscreen();
+      }
+      break;
+    case 'Escape':
+      d.location = controls.querySelector('a').href;
+      break;
+    default:
+      return;
+    }
+    e.preventDefault();
+  });
+  let touch = null;
+  show.addEventListener('touchstart', e => {
+    touch = e.changedTouches[0].clientX;
+  }, { passive: true });
+  show.addEventListener('touchend', e => {
+    let dx = e.changedTouches[0].clientX - touch;
+    if (Math.abs(dx) > 50) {
+      go(dx < 0 ? 1 : -1);
+    }
+  });
+
+  load().then(() => {
+    if (photos.length) {
+      go(1);
+    } else {
+      show.querySelector('.empty').hidden = false;
+    }
+  });
+})(document)
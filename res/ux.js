@@ -37,6 +37,13 @@
       resize_map();
     });
   }
+  d.querySelectorAll('.details .meta a[data-person]').forEach(a => {
+    let r = d.querySelector(`.details .item a.region[data-person="${a.dataset.person}"]`);
+    if (r) {
+      a.addEventListener('mouseenter', e => r.classList.add('hl'));
+      a.addEventListener('mouseleave', e => r.classList.remove('hl'));
+    }
+  });
   function prepare_map(cb) {
     let h = d.querySelector('head');
     var csslink = d.createElement('link');
@@ -68,7 +75,7 @@
       L.marker(p).addTo(map);
     })
   }
-  let group = d.querySelector('.group');
+  let group = d.querySelector('.group[data-positions]');
   let poss = (details && details.dataset.positions) || (group && group.dataset.positions);
   if (poss) {
     prepare_map((map) => {
@@ -96,6 +103,129 @@
       };
     })
   }
+  let pager = d.querySelector('nav.pager');
+  let next = pager && pager.querySelector('a[rel=next]');
+  if (next && 'IntersectionObserver' in window) {
+    // Infinite scroll: load the next window when the pager is shown.
+    let list = pager.previousElementSibling;
+    let loading = false;
+    let observer = new IntersectionObserver(entries => {
+      if (loading || !entries.some(e => e.isIntersecting)) {
+        return;
+      }
+      loading = true;
+      fetch(next.href + '&json=true')
+        .then(r => r.json())
+        .then(w => {
+          w.links.forEach(l => {
+            let item = d.createElement('div');
+            item.className = 'item' + (l.size[1] > l.size[0] ? ' portrait' : '');
+            if (l.title) {
+              let h = d.createElement('h2');
+              h.textContent = l.title;
+              item.append(h);
+            }
+            let a = d.createElement('a');
+            a.href = l.href;
+            let img = d.createElement('img');
+            img.src = `/img/${l.id}-s.jpg`;
+            img.width = l.size[0];
+            img.height = l.size[1];
+            img.alt = `Photo ${l.id}`;
+            a.append(img);
+            item.append(a);
+            if (l.lable) {
+              let s = d.createElement('span');
+              s.className = 'lable';
+              s.textContent = l.lable;
+              item.append(s);
+            }
+            list.append(item);
+          });
+          if (w.next) {
+            next.href = w.next;
+            loading = false;
+          } else {
+            next.remove();
+            observer.disconnect();
+          }
+        })
+        .catch(() => { loading = false; observer.disconnect(); });
+    }, { rootMargin: '400px' });
+    observer.observe(pager);
+  }
+  let fullmap = d.querySelector('.fullmap');
+  if (fullmap) {
+    prepare_map((map) => {
+      map.scrollWheelZoom.enable();
+      let layer = L.layerGroup().addTo(map);
+      function load() {
+        let b = map.getBounds();
+        let r = new XMLHttpRequest();
+        r.onload = function() {
+          layer.clearLayers();
+          JSON.parse(this.responseText).forEach(c => {
+            let icon = L.divIcon({
+              className: 'photocluster',
+              html: `<img src="/img/${c.photo}-s.jpg">` +
+                (c.count > 1 ? `<span>${c.count}</span>` : ''),
+              iconSize: [48, 48]
+            });
+            let m = L.marker([c.lat, c.lng], { icon: icon });
+            if (c.count > 1) {
+              m.on('click', () => map.setView(m.getLatLng(), map.getZoom() + 2));
+            } else {
+              m.bindPopup(`<a href="/img/${c.photo}"><img src="/img/${c.photo}-s.jpg"></a>`);
+            }
+            layer.addLayer(m);
+          });
+        };
+        r.open('GET', fullmap.dataset.clusters +
+               `?south=${b.getSouth()}&west=${b.getWest()}` +
+               `&north=${b.getNorth()}&east=${b.getEast()}` +
+               `&zoom=${map.getZoom()}`);
+        r.send(null);
+      }
+      map.on('moveend', load);
+      map.setView([30, 10], 2);
+    })
+  }
+  let geo = d.querySelector('main form.search div.geo');
+  if (geo) {
+    let input = geo.querySelector('input[name=bbox]');
+    function search_in(b) {
+      input.value = [b.getSouth(), b.getWest(), b.getNorth(), b.getEast()]
+        .map(v => v.toFixed(5)).join(',');
+      input.disabled = false;
+      geo.closest('form').submit();
+    }
+    function pick(map) {
+      map.on('boxzoomend', e => search_in(e.boxZoomBounds));
+      if (!geo.querySelector('.inview')) {
+        geo.insertAdjacentHTML(
+          'beforeend',
+          '<button type="button" class="inview">Search in map view</button>' +
+          ' <small>or shift-drag on the map to search in an area.</small>');
+        geo.querySelector('.inview')
+          .addEventListener('click', e => search_in(map.getBounds()));
+      }
+      d.getElementById('map').scrollIntoView();
+    }
+    geo.insertAdjacentHTML(
+      'afterbegin',
+      '<button type="button" class="pick">Pick area on map</button>');
+    geo.querySelector('.pick').addEventListener('click', e => {
+      e.target.remove();
+      if (map) {
+        pick(map);
+      } else {
+        prepare_map(map => {
+          map.setView([30, 10], 2);
+          pick(map);
+        });
+      }
+    });
+  }
 
   (function(form) {
     function prepareQtag(s) {
@@ -120,7 +250,7 @@
     list.className = 'list';
     let tags = form.querySelector('div.refs');
     form.insertBefore(list, tags);
-    let kindname = { 't': 'tag', 'p': 'person', 'l': 'place'}
+    let kindname = { 't': 'tag', 'p': 'person', 'l': 'place', 'c': 'camera'}
     let input = form.querySelector('input[name=q]');
     input.autocomplete = "off";
     input.addEventListener('keyup', e => {
@@ -0,0 +1,321 @@
+//! Correct the dates of photos from a camera with a wrong clock.
+//!
+//! A shift moves the date of all photos from a camera in a time
+//! window.  The original dates are recorded, so a shift can be undone.
+use super::result::Error;
+use crate::models::{Camera, ClockShift, Facet, Photo};
+use crate::schema::cameras::dsl as c;
+use crate::schema::clock_shift_photos::dsl as csp;
+use crate::schema::clock_shifts::dsl as cs;
+use crate::schema::photos::dsl as p;
+use crate::DbOpt;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::{delete, insert_into, update};
+use log::info;
+use serde::Deserialize;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Shiftclock {
+    #[structopt(flatten)]
+    db: DbOpt,
+
+    /// The camera to shift photos from, given by its slug.
+    #[structopt(long, short)]
+    camera: Option<String>,
+    /// Start of the time window, like 2019-07-14T08:00:00.
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    since: Option<NaiveDateTime>,
+    /// End of the time window, like 2019-07-28T20:00:00.
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    until: Option<NaiveDateTime>,
+    /// How much to shift the photos, like +1:00 or -0:12:30.
+    #[structopt(long, allow_hyphen_values = true)]
+    offset: Option<String>,
+    /// Actually shift the photos, rather than just list them.
+    #[structopt(long)]
+    apply: bool,
+    /// Undo a previous shift, given by its id.
+    #[structopt(long)]
+    undo: Option<i32>,
+    /// List previous shifts.
+    #[structopt(long)]
+    list: bool,
+}
+
+impl Shiftclock {
+    pub fn run(&self) -> Result<(), Error> {
+        let db = self.db.connect()?;
+        if self.list {
+            for (shift, camera) in list(&db)? {
+                println!(
+                    "#{}: {} {} - {} by {} ({} photos)",
+                    shift.id,
+                    camera.model,
+                    shift.since,
+                    shift.until,
+                    format_offset(shift.seconds),
+                    count_photos(&db, shift.id)?,
+                );
+            }
+        } else if let Some(shift) = self.undo {
+            let n = undo(&db, shift)?;
+            println!("Restored the date of {} photos.", n);
+        } else {
+            let form = ShiftForm {
+                camera: self.camera.clone().unwrap_or_default(),
+                since: self.since.map(|d| d.to_string()).unwrap_or_default(),
+                until: self.until.map(|d| d.to_string()).unwrap_or_default(),
+                offset: self.offset.clone().unwrap_or_default(),
+            };
+            let shift = form.validate(&db)?;
+            let offset = Duration::seconds(shift.seconds.into());
+            let photos = shift.affected(&db)?;
+            for photo in &photos {
+                if let Some(date) = photo.date {
+                    println!(
+                        "#{} {}: {} -> {}",
+                        photo.id,
+                        photo.path,
+                        date,
+                        date + offset,
+                    );
+                }
+            }
+            if self.apply {
+                let (id, n) = shift.apply(&db)?;
+                println!("Shifted {} photos.  Undo with --undo {}.", n, id);
+            } else {
+                println!(
+                    "Use --apply to shift these {} photos.",
+                    photos.len()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A clock shift as given in a form (or on the command line).
+///
+/// All fields are strings, to make it possible to redisplay a
+/// form with bad values.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ShiftForm {
+    pub camera: String,
+    pub since: String,
+    pub until: String,
+    pub offset: String,
+}
+
+impl ShiftForm {
+    /// True if nothing is filled in.
+    pub fn is_empty(&self) -> bool {
+        self.camera.is_empty()
+            && self.since.is_empty()
+            && self.until.is_empty()
+            && self.offset.is_empty()
+    }
+
+    pub fn validate(&self, db: &PgConnection) -> Result<Shift, Error> {
+        let camera = Camera::by_slug(&self.camera, db).map_err(|_| {
+            Error::Other(format!("Unknown camera {:?}", self.camera))
+        })?;
+        let since = parse_datetime(&self.since)?;
+        let until = parse_datetime(&self.until)?;
+        if until < since {
+            return Err(Error::Other("Empty time window".into()));
+        }
+        let seconds = parse_offset(&self.offset).ok_or_else(|| {
+            Error::Other(format!("Bad offset {:?}", self.offset))
+        })?;
+        Ok(Shift {
+            camera,
+            since,
+            until,
+            seconds,
+        })
+    }
+}
+
+/// A validated clock shift, that is not yet applied.
+pub struct Shift {
+    pub camera: Camera,
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    pub seconds: i32,
+}
+
+impl Shift {
+    /// The photos that would be shifted.
+    pub fn affected(&self, db: &PgConnection) -> QueryResult<Vec<Photo>> {
+        p::photos
+            .filter(p::camera_id.eq(self.camera.id))
+            .filter(p::date.ge(self.since))
+            .filter(p::date.le(self.until))
+            .order((p::date, p::id))
+            .load(db)
+    }
+
+    /// The date of a photo after this shift.
+    pub fn new_date(&self, photo: &Photo) -> Option<NaiveDateTime> {
+        photo
+            .date
+            .map(|d| d + Duration::seconds(self.seconds.into()))
+    }
+
+    /// Shift the photos, and record the original dates.
+    ///
+    /// Return the id of the shift and the number of shifted photos.
+    pub fn apply(&self, db: &PgConnection) -> Result<(i32, usize), Error> {
+        let offset = Duration::seconds(self.seconds.into());
+        Ok(db.transaction::<_, DieselError, _>(|| {
+            let shift = insert_into(cs::clock_shifts)
+                .values((
+                    cs::camera_id.eq(self.camera.id),
+                    cs::since.eq(self.since),
+                    cs::until.eq(self.until),
+                    cs::seconds.eq(self.seconds),
+                ))
+                .get_result::<ClockShift>(db)?;
+            let photos = self.affected(db)?;
+            for photo in &photos {
+                insert_into(csp::clock_shift_photos)
+                    .values((
+                        csp::shift_id.eq(shift.id),
+                        csp::photo_id.eq(photo.id),
+                        csp::old_date.eq(photo.date),
+                        csp::old_utc_date.eq(photo.utc_date),
+                    ))
+                    .execute(db)?;
+                update(p::photos.find(photo.id))
+                    .set((
+                        p::date.eq(photo.date.map(|d| d + offset)),
+                        p::utc_date.eq(photo.utc_date.map(|d| d + offset)),
+                    ))
+                    .execute(db)?;
+            }
+            info!(
+                "Shifted {} photos from {} by {}",
+                photos.len(),
+                self.camera.model,
+                format_offset(self.seconds),
+            );
+            Ok((shift.id, photos.len()))
+        })?)
+    }
+}
+
+/// Previous shifts, newest first.
+pub fn list(db: &PgConnection) -> Result<Vec<(ClockShift, Camera)>, Error> {
+    Ok(cs::clock_shifts
+        .inner_join(c::cameras)
+        .order(cs::created.desc())
+        .load(db)?)
+}
+
+pub fn count_photos(db: &PgConnection, shift: i32) -> Result<i64, Error> {
+    Ok(csp::clock_shift_photos
+        .filter(csp::shift_id.eq(shift))
+        .count()
+        .get_result(db)?)
+}
+
+/// Undo a shift, restoring the original dates of the photos.
+///
+/// If shifts overlap, they should be undone in reverse order.
+/// Return the number of restored photos.
+pub fn undo(db: &PgConnection, shift: i32) -> Result<usize, Error> {
+    Ok(db.transaction::<_, DieselError, _>(|| {
+        let old = csp::clock_shift_photos
+            .filter(csp::shift_id.eq(shift))
+            .select((csp::photo_id, csp::old_date, csp::old_utc_date))
+            .load::<(i32, Option<NaiveDateTime>, Option<DateTime<Utc>>)>(db)?;
+        for (photo, date, utc_date) in &old {
+            update(p::photos.find(photo))
+                .set((p::date.eq(date), p::utc_date.eq(utc_date)))
+                .execute(db)?;
+        }
+        delete(csp::clock_shift_photos.filter(csp::shift_id.eq(shift)))
+            .execute(db)?;
+        if delete(cs::clock_shifts.find(shift)).execute(db)? == 0 {
+            return Err(DieselError::NotFound);
+        }
+        info!("Undid shift #{} for {} photos", shift, old.len());
+        Ok(old.len())
+    })?)
+}
+
+/// Parse a date and time, with or without seconds.
+///
+/// Both the format used by html datetime-local inputs (with a `T`)
+/// and with a space (as shown by rphotos) are accepted.
+pub fn parse_datetime(s: &str) -> Result<NaiveDateTime, Error> {
+    let s = s.trim().replacen(' ', "T", 1);
+    NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M"))
+        .map_err(Error::from)
+}
+
+/// Parse an offset as [+-]h:mm[:ss], returning a number of seconds.
+pub fn parse_offset(s: &str) -> Option<i32> {
+    let s = s.trim();
+    let (sign, s) = if let Some(s) = s.strip_prefix('-') {
+        (-1, s)
+    } else {
+        (1, s.strip_prefix('+').unwrap_or(s))
+    };
+    let parts = s
+        .split(':')
+        .map(|p| p.parse::<u16>().ok().map(i32::from))
+        .collect::<Option<Vec<_>>>()?;
+    match parts.as_slice() {
+        [h, m] if *m < 60 => Some(sign * (h * 3600 + m * 60)),
+        [h, m, s] if *m < 60 && *s < 60 => {
+            Some(sign * (h * 3600 + m * 60 + s))
+        }
+        _ => None,
+    }
+}
+
+/// Format a number of seconds as an offset, inverse of `parse_offset`.
+pub fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let s = seconds.abs();
+    format!("{}{}:{:02}:{:02}", sign, s / 3600, s / 60 % 60, s % 60)
+}
+
+#[test]
+fn parse_offset_hm() {
+    assert_eq!(parse_offset("+1:30"), Some(5400));
+}
+
+#[test]
+fn parse_offset_negative() {
+    assert_eq!(parse_offset("-0:12:30"), Some(-750));
+}
+
+#[test]
+fn parse_offset_bad() {
+    assert_eq!(parse_offset("1:75"), None);
+    assert_eq!(parse_offset("an hour"), None);
+}
+
+#[test]
+fn format_offset_roundtrip() {
+    assert_eq!(format_offset(-750), "-0:12:30");
+    assert_eq!(parse_offset(&format_offset(93784)), Some(93784));
+}
+
+#[test]
+fn parse_datetime_html() {
+    assert_eq!(
+        parse_datetime("2019-07-14T08:30").ok(),
+        Some(chrono::NaiveDate::from_ymd(2019, 7, 14).and_hms(8, 30, 0)),
+    );
+}
@@ -1,5 +1,7 @@
 use super::result::Error;
-use crate::models::{Camera, Modification, Photo};
+use crate::models::{
+    Camera, CaptureDetails, Lens, Modification, Person, Photo, Region,
+};
 use crate::myexif::ExifData;
 use crate::photosdir::PhotosDir;
 use crate::{DbOpt, DirOpt};
@@ -85,6 +87,40 @@ fn save_photo(
             photo
         }
     };
+    let mut details = capture_details(db, exif)?;
+    if details.utc_date.is_some() && photo.has_corrected_date(db)? {
+        details.utc_date = None;
+    }
+    if details.differs_from(&photo) {
+        info!("Capture details for #{}: {:?}", photo.id, details);
+        details.save_for(db, photo.id)?;
+    }
+    if photo.import_caption(db, exif.title(), exif.description())? {
+        let (title, description) = (exif.title(), exif.description());
+        info!("Caption for #{}: {:?}, {:?}", photo.id, title, description);
+    }
+    if !exif.faces.is_empty() {
+        let known = photo.load_regions(db)?;
+        for face in &exif.faces {
+            let region = Region::from_mwg(
+                face.x,
+                face.y,
+                face.w,
+                face.h,
+                photo.rotation,
+            );
+            let region = match region {
+                Some(region) => region,
+                None => continue,
+            };
+            let person = Person::get_or_create_name(db, &face.name)?;
+            if known.iter().any(|(p, _)| p.id == person.id) {
+                continue;
+            }
+            info!("Face of {} in #{}", person.person_name, photo.id);
+            Photo::set_region(db, photo.id, person.id, Some(region))?;
+        }
+    }
     if let Some((lat, long)) = exif.position() {
         debug!("Position for {} is {} {}", file_path, lat, long);
         use crate::schema::positions::dsl::*;
@@ -109,6 +145,7 @@ fn save_photo(
                     photo_id.eq(photo.id),
                     latitude.eq((lat * 1e6) as i32),
                     longitude.eq((long * 1e6) as i32),
+                    source.eq("exif"),
                 ))
                 .execute(db)
                 .expect("Insert image position");
@@ -127,3 +164,27 @@ fn find_camera(
     }
     Ok(None)
 }
+
+fn capture_details(
+    db: &PgConnection,
+    exif: &ExifData,
+) -> Result<CaptureDetails, Error> {
+    let lens =
+        if let Some(model) = exif.lens.as_ref().filter(|m| !m.is_empty()) {
+            Some(Lens::get_or_create(db, model)?)
+        } else {
+            None
+        };
+    Ok(CaptureDetails {
+        lens_id: lens.map(|l| l.id),
+        exposure_time: exif.exposure_time.map(|t| t as f32),
+        aperture: exif.aperture.map(|a| a as f32),
+        iso: exif.iso.map(|iso| iso as i32),
+        focal_length: exif.focal_length.map(|l| l as f32),
+        focal_length_35: exif.focal_length_35.map(|l| l as i16),
+        flash: exif.flash(),
+        altitude: exif.altitude().map(|a| a as f32),
+        direction: exif.direction().map(|d| d as f32),
+        utc_date: exif.utc_date(),
+    })
+}
@@ -0,0 +1,446 @@
+//! Set positions of photos from gps tracks, or from other photos
+//! taken close in time.
+use super::clock_shift::parse_offset;
+use super::result::Error;
+use crate::fetch_places;
+use crate::models::Photo;
+use crate::schema::photo_places::dsl as pl;
+use crate::schema::photos::dsl as p;
+use crate::schema::positions::dsl as pos;
+use crate::DbOpt;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::dsl::{exists, not};
+use diesel::prelude::*;
+use diesel::{delete, insert_into};
+use log::{debug, info};
+use serde::Deserialize;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Geotag {
+    #[structopt(flatten)]
+    db: DbOpt,
+    /// How much to add to the camera time to get the correct time,
+    /// like +0:02:30 if the camera is two and a half minutes late.
+    #[structopt(long, default_value = "0:00", allow_hyphen_values = true)]
+    offset: String,
+    /// Time zone of the camera clock, for photos where it is not
+    /// known from exif, like Europe/Stockholm.  Required if there are
+    /// such photos.
+    #[structopt(long)]
+    timezone: Option<Tz>,
+    /// Max time (in seconds) from a photo to a track point to use.
+    #[structopt(long, default_value = "300")]
+    max_gap: i64,
+    /// Replace existing positions, even if they are from exif or set
+    /// by hand.  By default, only positions earlier set from gpx
+    /// tracks are replaced.
+    #[structopt(long)]
+    overwrite: bool,
+    /// Queue the tagged photos for fetching places.
+    #[structopt(long)]
+    places: bool,
+    /// Only show what would be done.
+    #[structopt(long, short = "n")]
+    dry_run: bool,
+    /// The gpx files to read.
+    #[structopt(required = true)]
+    files: Vec<PathBuf>,
+}
+
+impl Geotag {
+    pub fn run(&self) -> Result<(), Error> {
+        let offset = parse_offset(&self.offset).ok_or_else(|| {
+            Error::Other(format!("Bad offset {:?}", self.offset))
+        })?;
+        let mut track = Track::default();
+        for file in &self.files {
+            track.read_gpx(file)?;
+        }
+        track.sort();
+        let (start, end) = track.span().ok_or_else(|| {
+            Error::Other("No track points found".to_string())
+        })?;
+        info!("Track from {} to {}", start, end);
+        let db = self.db.connect()?;
+        // Local dates may be off by a day, use a broad range.
+        let margin = Duration::days(1);
+        let photos = p::photos
+            .filter(p::date.ge((start - margin).naive_utc()))
+            .filter(p::date.le((end + margin).naive_utc()))
+            .order(p::date)
+            .load::<Photo>(&db)?;
+        if self.timezone.is_none() {
+            let n = photos.iter().filter(|p| p.utc_date.is_none()).count();
+            if n > 0 {
+                return Err(Error::Other(format!(
+                    "The time zone of {} photos is unknown, use --timezone",
+                    n,
+                )));
+            }
+        }
+        let max_gap = Duration::seconds(self.max_gap);
+        let (mut tagged, mut kept) = (0, 0);
+        for photo in photos {
+            let time = match self.utc_time(&photo) {
+                Some(time) => time + Duration::seconds(offset.into()),
+                None => continue,
+            };
+            let (lat, lng) = match track.position_at(time, max_gap) {
+                Some(position) => position,
+                None => continue,
+            };
+            let old_source = pos::positions
+                .filter(pos::photo_id.eq(photo.id))
+                .select(pos::source)
+                .first::<Option<String>>(&db)
+                .optional()?;
+            let replace = match old_source {
+                None => false,
+                Some(ref source) if source.as_deref() == Some("gpx") => true,
+                Some(_) if self.overwrite => true,
+                Some(source) => {
+                    debug!("Keeping {:?} position of #{}", source, photo.id);
+                    kept += 1;
+                    continue;
+                }
+            };
+            println!("#{} {} at {:.6} {:.6}", photo.id, photo.path, lat, lng);
+            tagged += 1;
+            if !self.dry_run {
+                self.save(&db, photo.id, lat, lng, replace)?;
+            }
+        }
+        println!(
+            "Found position for {} photos{}, kept {} existing positions.",
+            tagged,
+            if self.dry_run { " (dry run)" } else { "" },
+            kept,
+        );
+        Ok(())
+    }
+
+    /// The time of a photo in utc, not adjusted by the offset.
+    fn utc_time(&self, photo: &Photo) -> Option<DateTime<Utc>> {
+        photo.utc_date.or_else(|| {
+            let date = photo.date?;
+            let local =
+                self.timezone?.from_local_datetime(&date).earliest()?;
+            Some(local.with_timezone(&Utc))
+        })
+    }
+
+    fn save(
+        &self,
+        db: &PgConnection,
+        photo: i32,
+        lat: f64,
+        lng: f64,
+        replace: bool,
+    ) -> Result<(), Error> {
+        let (lat, lng) = ((lat * 1e6) as i32, (lng * 1e6) as i32);
+        save_position(db, photo, lat, lng, "gpx")?;
+        if replace {
+            // The places was found for the old position.
+            delete(pl::photo_places.filter(pl::photo_id.eq(photo)))
+                .execute(db)?;
+        }
+        if self.places {
+            fetch_places::enqueue(db, photo)?;
+        }
+        Ok(())
+    }
+}
+
+/// Store the position (in microdegrees) of a photo.
+pub fn save_position(
+    db: &PgConnection,
+    photo: i32,
+    lat: i32,
+    lng: i32,
+    source: &str,
+) -> Result<(), diesel::result::Error> {
+    insert_into(pos::positions)
+        .values((
+            pos::photo_id.eq(photo),
+            pos::latitude.eq(lat),
+            pos::longitude.eq(lng),
+            pos::source.eq(source),
+        ))
+        .on_conflict(pos::photo_id)
+        .do_update()
+        .set((
+            pos::latitude.eq(lat),
+            pos::longitude.eq(lng),
+            pos::source.eq(source),
+        ))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Store a position (in microdegrees) borrowed from nearby photos.
+///
+/// The source is 'nearby'.  A photo that got a position after the
+/// proposal was made is left as it is.  Return true if the position
+/// was stored.
+pub fn save_nearby_position(
+    db: &PgConnection,
+    photo: i32,
+    lat: i32,
+    lng: i32,
+) -> Result<bool, diesel::result::Error> {
+    let n = insert_into(pos::positions)
+        .values((
+            pos::photo_id.eq(photo),
+            pos::latitude.eq(lat),
+            pos::longitude.eq(lng),
+            pos::source.eq("nearby"),
+        ))
+        .on_conflict_do_nothing()
+        .execute(db)?;
+    Ok(n > 0)
+}
+
+/// A proposed position for a photo, based on photos taken close to
+/// it in time.
+pub struct Proposal {
+    pub photo: Photo,
+    /// Latitude in microdegrees.
+    pub lat: i32,
+    /// Longitude in microdegrees.
+    pub lng: i32,
+}
+
+/// Options for proposing positions from nearby photos.
+#[derive(Deserialize)]
+pub struct GeotagForm {
+    /// Max time (in seconds) from a positioned photo.
+    #[serde(default = "default_window")]
+    pub window: u32,
+    /// Max number of proposals.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_window() -> u32 {
+    600
+}
+fn default_limit() -> usize {
+    100
+}
+
+/// Propose positions for photos that has none.
+///
+/// The position is borrowed from a positioned photo taken within
+/// `window` of the photo, or interpolated between positioned photos
+/// taken before and after it.  Photos are compared by the instant
+/// they were taken (see `Photo::instant_expr`), so photos with a
+/// known time zone can be matched across time zones.  Newest photos
+/// are proposed first.  Only a limited number of photos are looked
+/// through, so there may be fewer than `limit` proposals even if
+/// more photos could get a position.
+pub fn propose_nearby(
+    db: &PgConnection,
+    window: Duration,
+    limit: usize,
+) -> Result<Vec<Proposal>, diesel::result::Error> {
+    /// Number of unpositioned photos to consider at a time.
+    const BATCH: i64 = 500;
+    /// Max number of batches to look through for proposals.
+    const MAX_BATCHES: usize = 20;
+    let mut result = Vec::new();
+    let mut after: Option<(DateTime<Utc>, i32)> = None;
+    for _ in 0..MAX_BATCHES {
+        if result.len() >= limit {
+            break;
+        }
+        let mut query = p::photos
+            .filter(p::date.is_not_null())
+            .filter(not(exists(
+                pos::positions.filter(pos::photo_id.eq(p::id)),
+            )))
+            .order((Photo::instant_expr().desc(), p::id.desc()))
+            .limit(BATCH)
+            .into_boxed();
+        if let Some((time, id)) = after {
+            query = query.filter(
+                Photo::instant_expr()
+                    .lt(time)
+                    .or(Photo::instant_expr().eq(time).and(p::id.lt(id))),
+            );
+        }
+        let batch = query.load::<Photo>(db)?;
+        after = match batch.last() {
+            Some(photo) => instant(photo).map(|time| (time, photo.id)),
+            None => break,
+        };
+        let times = batch.iter().filter_map(instant).collect::<Vec<_>>();
+        let (first, last) = match (times.iter().min(), times.iter().max()) {
+            (Some(first), Some(last)) => (*first - window, *last + window),
+            _ => break,
+        };
+        let points = pos::positions
+            .inner_join(p::photos)
+            .select((Photo::instant_expr(), pos::latitude, pos::longitude))
+            .filter(Photo::instant_expr().between(first, last))
+            .load::<(Option<DateTime<Utc>>, i32, i32)>(db)?
+            .into_iter()
+            .filter_map(|(time, lat, lng)| {
+                Some((time?, f64::from(lat) / 1e6, f64::from(lng) / 1e6))
+            })
+            .collect();
+        let mut track = Track { points };
+        track.sort();
+        result.extend(batch.into_iter().filter_map(|photo| {
+            let (lat, lng) = track.position_at(instant(&photo)?, window)?;
+            Some(Proposal {
+                photo,
+                lat: (lat * 1e6) as i32,
+                lng: (lng * 1e6) as i32,
+            })
+        }));
+    }
+    result.truncate(limit);
+    Ok(result)
+}
+
+/// The instant a photo was taken, like `Photo::instant_expr`.
+fn instant(photo: &Photo) -> Option<DateTime<Utc>> {
+    photo
+        .utc_date
+        .or_else(|| photo.date.map(|d| Utc.from_utc_datetime(&d)))
+}
+
+/// A gps track, as a list of (time, lat, lng) points.
+#[derive(Default)]
+struct Track {
+    points: Vec<(DateTime<Utc>, f64, f64)>,
+}
+
+impl Track {
+    fn read_gpx(&mut self, path: &Path) -> Result<(), Error> {
+        let text = read_to_string(path)?;
+        let n = self
+            .parse_gpx(&text)
+            .map_err(|e| Error::in_file(&e, path))?;
+        info!("Read {} track points from {}", n, path.display());
+        Ok(())
+    }
+
+    /// Add track points from a gpx document.
+    ///
+    /// Waypoints and route points with a time are also used.
+    /// Return the number of points added.
+    fn parse_gpx(&mut self, text: &str) -> Result<usize, String> {
+        let doc =
+            roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+        let before = self.points.len();
+        for pt in doc.descendants().filter(|n| {
+            n.has_tag_name("trkpt")
+                || n.has_tag_name("wpt")
+                || n.has_tag_name("rtept")
+        }) {
+            let coord = |name| pt.attribute(name).and_then(|v| v.parse().ok());
+            let time = pt
+                .children()
+                .find(|n| n.has_tag_name("time"))
+                .and_then(|n| n.text())
+                .and_then(|t| DateTime::parse_from_rfc3339(t.trim()).ok());
+            if let (Some(lat), Some(lng), Some(time)) =
+                (coord("lat"), coord("lon"), time)
+            {
+                self.points.push((time.with_timezone(&Utc), lat, lng));
+            }
+        }
+        Ok(self.points.len() - before)
+    }
+
+    fn sort(&mut self) {
+        self.points.sort_by_key(|p| p.0);
+    }
+
+    fn span(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        Some((self.points.first()?.0, self.points.last()?.0))
+    }
+
+    /// Get the position at a given time.
+    ///
+    /// If there are track points within max_gap both before and
+    /// after the time, the position is interpolated between them.
+    /// Otherwise, the closest point within max_gap is used.
+    fn position_at(
+        &self,
+        time: DateTime<Utc>,
+        max_gap: Duration,
+    ) -> Option<(f64, f64)> {
+        let i = match self.points.binary_search_by_key(&time, |p| p.0) {
+            Ok(i) => return Some((self.points[i].1, self.points[i].2)),
+            Err(i) => i,
+        };
+        let before = i.checked_sub(1).map(|i| self.points[i]);
+        let before = before.filter(|p| time - p.0 <= max_gap);
+        let after = self.points.get(i).filter(|p| p.0 - time <= max_gap);
+        match (before, after) {
+            (Some((t0, lat0, lng0)), Some(&(t1, lat1, lng1))) => {
+                let part = (time - t0).num_milliseconds() as f64
+                    / (t1 - t0).num_milliseconds() as f64;
+                Some((
+                    lat0 + part * (lat1 - lat0),
+                    lng0 + part * (lng1 - lng0),
+                ))
+            }
+            (Some((_, lat, lng)), None) | (None, Some(&(_, lat, lng))) => {
+                Some((lat, lng))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_track() -> Track {
+    let mut track = Track::default();
+    track
+        .parse_gpx(
+            r#"<?xml version="1.0"?>
+<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+ <trk><trkseg>
+  <trkpt lat="59.0" lon="18.0"><time>2019-07-14T08:00:00Z</time></trkpt>
+  <trkpt lat="59.1" lon="18.2"><time>2019-07-14T08:01:00Z</time></trkpt>
+  <trkpt lat="60.0" lon="18.0"><time>2019-07-14T10:00:00Z</time></trkpt>
+ </trkseg></trk>
+</gpx>"#,
+        )
+        .unwrap();
+    track.sort();
+    track
+}
+
+#[test]
+fn parse_gpx_points() {
+    assert_eq!(test_track().points.len(), 3);
+}
+
+#[test]
+fn interpolate_position() {
+    let time = Utc.ymd(2019, 7, 14).and_hms(8, 0, 30);
+    let (lat, lng) = test_track()
+        .position_at(time, Duration::seconds(300))
+        .unwrap();
+    assert!((lat - 59.05).abs() < 1e-9);
+    assert!((lng - 18.1).abs() < 1e-9);
+}
+
+#[test]
+fn position_in_gap() {
+    let track = test_track();
+    let max_gap = Duration::seconds(300);
+    let near = Utc.ymd(2019, 7, 14).and_hms(8, 4, 0);
+    assert_eq!(track.position_at(near, max_gap), Some((59.1, 18.2)));
+    let far = Utc.ymd(2019, 7, 14).and_hms(9, 0, 0);
+    assert_eq!(track.position_at(far, max_gap), None);
+}
@@ -1,4 +1,6 @@
+pub mod clock_shift;
 pub mod findphotos;
+pub mod geotag;
 pub mod makepublic;
 pub mod precache;
 pub mod result;
@@ -1,12 +1,26 @@
+mod classify;
+mod local;
+mod queue;
+
+use self::classify::Classifier;
+pub use self::classify::Reclassify;
+use self::local::PlaceIndex;
+pub use self::queue::enqueue;
+use crate::adm::result::Error as AdmError;
 use crate::models::{Coord, Place};
 use crate::DbOpt;
 use diesel;
 use diesel::prelude::*;
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use log::{debug, info, warn};
-use reqwest::{self, Client, Response};
+use reqwest::{self, Client, StatusCode};
 use serde_json::Value;
 use slug::slugify;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -17,35 +31,55 @@ pub struct Fetchplaces {
     #[structopt(flatten)]
     overpass: OverpassOpt,
 
-    /// Max number of photos to use for --auto
-    #[structopt(long, short, default_value = "5")]
-    limit: i64,
-    /// Fetch data for photos with position but lacking places.
+    /// Max number of queued photos to fetch places for with --auto.
+    ///
+    /// By default, all queued photos are handled.
+    #[structopt(long, short)]
+    limit: Option<i64>,
+    /// Queue photos with position but lacking places, and fetch
+    /// places for all queued photos.
     #[structopt(long, short)]
     auto: bool,
+    /// Photos closer than this many meters share a lookup in --auto.
+    #[structopt(long, default_value = "100")]
+    cluster_distance: u32,
+    /// Max number of lookups per second in --auto.
+    #[structopt(long, default_value = "1")]
+    max_rate: f64,
+    /// Number of lookups to run in parallel in --auto.
+    #[structopt(long, default_value = "1")]
+    concurrency: usize,
+    /// Derive the place hierarchy from already fetched places,
+    /// without fetching anything.
+    #[structopt(long)]
+    link_parents: bool,
     /// Image ids to fetch place data for
     photos: Vec<i32>,
 }
 
 impl Fetchplaces {
-    pub fn run(&self) -> Result<(), super::adm::result::Error> {
+    pub fn run(&self) -> Result<(), AdmError> {
         let db = self.db.connect()?;
-        if self.auto {
-            println!("Should find {} photos to fetch places for", self.limit);
+        if self.link_parents {
             use crate::schema::photo_places::dsl as place;
-            use crate::schema::positions::dsl as pos;
-            let result = pos::positions
-                .select((pos::photo_id, (pos::latitude, pos::longitude)))
-                .filter(pos::photo_id.ne_all(
-                    place::photo_places.select(place::photo_id).distinct(),
-                ))
-                .order(pos::photo_id.desc())
-                .limit(self.limit)
-                .load::<(i32, Coord)>(&db)?;
-            for (photo_id, coord) in result {
-                println!("Find places for #{}, {:?}", photo_id, coord);
-                self.overpass.update_image_places(&db, photo_id)?;
+            let photos = place::photo_places
+                .select(place::photo_id)
+                .distinct()
+                .load::<i32>(&db)?;
+            println!("Linking places of {} photos", photos.len());
+            for photo in photos {
+                Place::link_parents(&db, photo)?;
             }
+        } else if self.auto {
+            let n = queue::enqueue_missing(&db)?;
+            println!("Queued {} more photos to fetch places for", n);
+            let opt = queue::QueueOpt {
+                limit: self.limit,
+                cluster_distance: self.cluster_distance,
+                max_rate: self.max_rate,
+                concurrency: self.concurrency,
+            };
+            queue::run(&db, &self.overpass, &opt)?.print();
         } else {
             for photo in &self.photos {
                 self.overpass.update_image_places(&db, *photo)?;
@@ -63,7 +97,41 @@ pub struct OverpassOpt {
     /// See https://wiki.openstreetmap.org/wiki/Overpass_API for
     /// available servers and policies.
     #[structopt(long, env = "OVERPASS_URL")]
-    overpass_url: String,
+    overpass_url: Option<String>,
+    /// Find places in a local GeoJSON file instead of asking overpass.
+    ///
+    /// The file should contain (multi)polygons for areas with osm
+    /// tags as properties, e.g. as exported by `osmium export`.
+    #[structopt(long, env = "RPHOTOS_PLACES_FILE")]
+    places_file: Option<PathBuf>,
+    /// Json file with rules for place levels and preferred languages.
+    ///
+    /// By default, built-in rules tuned for Sweden are used.
+    #[structopt(
+        long,
+        env = "RPHOTOS_PLACES_CONFIG",
+        parse(try_from_str = Classifier::load)
+    )]
+    places_config: Option<Arc<Classifier>>,
+    /// Number of times to retry an overpass request that failed
+    /// with "too many requests" or "gateway timeout".
+    ///
+    /// The delay is doubled for each retry, starting at one second.
+    #[structopt(long, default_value = "4")]
+    overpass_retries: u32,
+    #[structopt(skip)]
+    index: Arc<Mutex<Option<Arc<PlaceIndex>>>>,
+}
+
+/// An area (as returned by overpass is_in) containing a photo.
+#[derive(Clone, Debug)]
+pub struct Area {
+    pub osm_id: i64,
+    pub name: String,
+    pub level: i16,
+    /// Names in the preferred languages, as (language, name).
+    pub names: Vec<(String, String)>,
+    pub tags: Value,
 }
 
 impl OverpassOpt {
@@ -81,182 +149,166 @@ impl OverpassOpt {
             .map_err(|e| Error::Db(image, e))?
             .ok_or_else(|| Error::NoPosition(image))?;
         debug!("Should get places for #{} at {:?}", image, coord);
-        let data = Client::new()
-            .post(&self.overpass_url)
-            .body(format!("[out:json];is_in({},{});out;", coord.x, coord.y))
-            .send()
-            .and_then(Response::error_for_status)
-            .and_then(|mut r| r.json::<Value>())
+        let areas = self.areas_at(image, &coord)?;
+        self.store_areas(c, image, &areas)
+    }
+
+    /// Get the areas containing a position.
+    fn areas_at(&self, image: i32, coord: &Coord) -> Result<Vec<Area>, Error> {
+        if self.places_file.is_some() {
+            Ok(self.local_index()?.lookup(coord))
+        } else {
+            self.fetch_areas(image, coord)
+        }
+    }
+
+    /// Store areas as the places of a photo.
+    fn store_areas(
+        &self,
+        c: &PgConnection,
+        image: i32,
+        areas: &[Area],
+    ) -> Result<(), Error> {
+        for area in areas {
+            debug!("{}: {} (level {})", area.osm_id, area.name, area.level);
+            let place =
+                get_or_create_place(c, area.osm_id, &area.name, area.level)
+                    .map_err(|e| Error::Db(image, e))?;
+            if place.osm_id.is_none() {
+                debug!("Matched {:?} by name, update osm info", place);
+                use crate::schema::places::dsl::*;
+                diesel::update(places)
+                    .filter(id.eq(place.id))
+                    .set((
+                        osm_id.eq(Some(area.osm_id)),
+                        osm_level.eq(area.level),
+                    ))
+                    .execute(c)
+                    .map_err(|e| Error::Db(image, e))?;
+            }
+            save_tags_and_names(c, &place, area)
+                .map_err(|e| Error::Db(image, e))?;
+            use crate::models::PhotoPlace;
+            use crate::schema::photo_places::dsl::*;
+            let q = photo_places
+                .filter(photo_id.eq(image))
+                .filter(place_id.eq(place.id));
+            if q.first::<PhotoPlace>(c).is_ok() {
+                debug!(
+                    "Photo #{} already has {} ({})",
+                    image, place.id, place.place_name
+                );
+            } else {
+                diesel::insert_into(photo_places)
+                    .values((photo_id.eq(image), place_id.eq(place.id)))
+                    .execute(c)
+                    .map_err(|e| Error::Db(image, e))?;
+            }
+        }
+        Place::link_parents(c, image).map_err(|e| Error::Db(image, e))?;
+        Ok(())
+    }
+
+    fn fetch_areas(
+        &self,
+        image: i32,
+        coord: &Coord,
+    ) -> Result<Vec<Area>, Error> {
+        let url = self.overpass_url.as_ref().ok_or(Error::NoSource)?;
+        let data = self
+            .query(url, &format!("is_in({},{})", coord.x, coord.y))
             .map_err(|e| Error::Server(image, e))?;
 
+        let mut result = Vec::new();
         if let Some(elements) = data
             .as_object()
             .and_then(|o| o.get("elements"))
             .and_then(Value::as_array)
         {
+            let classifier = self.classifier();
             for obj in elements {
-                if let (Some(t_osm_id), Some((name, level))) =
-                    (osm_id(obj), name_and_level(obj))
+                if let Some(area) = osm_id(obj)
+                    .and_then(|id| classifier.area(id, obj.get("tags")?))
                 {
-                    debug!("{}: {} (level {})", t_osm_id, name, level);
-                    let place = get_or_create_place(c, t_osm_id, name, level)
-                        .map_err(|e| Error::Db(image, e))?;
-                    if place.osm_id.is_none() {
-                        debug!("Matched {:?} by name, update osm info", place);
-                        use crate::schema::places::dsl::*;
-                        diesel::update(places)
-                            .filter(id.eq(place.id))
-                            .set((
-                                osm_id.eq(Some(t_osm_id)),
-                                osm_level.eq(level),
-                            ))
-                            .execute(c)
-                            .map_err(|e| Error::Db(image, e))?;
-                    }
-                    use crate::models::PhotoPlace;
-                    use crate::schema::photo_places::dsl::*;
-                    let q = photo_places
-                        .filter(photo_id.eq(image))
-                        .filter(place_id.eq(place.id));
-                    if q.first::<PhotoPlace>(c).is_ok() {
-                        debug!(
-                            "Photo #{} already has {} ({})",
-                            image, place.id, place.place_name
-                        );
-                    } else {
-                        diesel::insert_into(photo_places)
-                            .values((
-                                photo_id.eq(image),
-                                place_id.eq(place.id),
-                            ))
-                            .execute(c)
-                            .map_err(|e| Error::Db(image, e))?;
-                    }
+                    result.push(area);
                 } else {
                     info!("Unused area: {}", obj);
                 }
             }
         }
-        Ok(())
+        Ok(result)
     }
-}
 
-fn osm_id(obj: &Value) -> Option<i64> {
-    obj.get("id").and_then(Value::as_i64)
-}
+    /// Get the tags of an area from overpass.
+    fn fetch_tags(&self, osm_id: i64) -> Result<Option<Value>, AdmError> {
+        let url = self.overpass_url.as_ref().ok_or(Error::NoSource)?;
+        let data =
+            self.query(url, &format!("area({})", osm_id)).map_err(|e| {
+                AdmError::Other(format!(
+                    "Failed to get area {}: {}",
+                    osm_id, e
+                ))
+            })?;
+        Ok(data
+            .get("elements")
+            .and_then(|e| e.get(0))
+            .and_then(|e| e.get("tags"))
+            .cloned())
+    }
 
-fn name_and_level(obj: &Value) -> Option<(&str, i16)> {
-    if let Some(tags) = obj.get("tags") {
-        let name = tags
-            .get("name:sv")
-            //.or_else(|| tags.get("name:en"))
-            .or_else(|| tags.get("name"))
-            .and_then(Value::as_str);
-        let level = tags
-            .get("admin_level")
-            .and_then(Value::as_str)
-            .and_then(|l| l.parse().ok())
-            .or_else(|| match tag_str(tags, "leisure") {
-                Some("garden") => Some(18),
-                Some("nature_reserve") => Some(12),
-                Some("park") => Some(14),
-                Some("pitch") => Some(15),
-                Some("playground") => Some(16),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "tourism") {
-                Some("attraction") => Some(16),
-                Some("theme_park") | Some("zoo") => Some(14),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "boundary") {
-                Some("national_park") => Some(14),
-                Some("historic") => Some(7), // Seems to be mainly "Landskap"
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "landuse") {
-                Some("allotments") => Some(14),
-                Some("commercial") => Some(12),
-                Some("grass") => Some(13),
-                Some("industrial") => Some(11),
-                Some("residential") => Some(11),
-                Some("retail") => Some(13),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "highway") {
-                Some("pedestrian") => Some(15), // torg
-                Some("rest_area") => Some(16),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "public_transport") {
-                Some("station") => Some(18),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "amenity") {
-                Some("bus_station") => Some(16),
-                Some("exhibition_center") => Some(20),
-                Some("kindergarten") => Some(15),
-                Some("place_of_worship") => Some(15),
-                Some("school") => Some(14),
-                Some("university") => Some(12),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "aeroway") {
-                Some("aerodrome") => Some(14),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "water") {
-                Some("lake") => Some(15),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "waterway") {
-                Some("riverbank") => Some(16),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "man_made") {
-                Some("bridge") => Some(17),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "place") {
-                Some("city_block") => Some(17),
-                Some("island") => Some(13),
-                Some("islet") => Some(17),
-                Some("penisula") => Some(13),
-                Some("region") => Some(8),
-                Some("square") => Some(18),
-                Some("suburb") => Some(11),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "natural") {
-                Some("bay") => Some(14),
-                Some("wood") => Some(14),
-                Some("scrub") => Some(18),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "building") {
-                Some("exhibition_center") => Some(19),
-                Some("sports_hall") => Some(19),
-                Some(_) => Some(20),
-                _ => None,
-            })
-            .or_else(|| match tag_str(tags, "political_division") {
-                Some("canton") => Some(9),
-                _ => None,
-            });
-        if let (Some(name), Some(level)) = (name, level) {
-            debug!("{} is level {}", name, level);
-            Some((name, level))
-        } else {
-            None
+    /// Run an overpass query, with retries if the server is busy.
+    fn query(&self, url: &str, query: &str) -> Result<Value, reqwest::Error> {
+        let client = Client::new();
+        let mut delay = Duration::from_secs(1);
+        let mut retries = 0;
+        loop {
+            let response = client
+                .post(url)
+                .body(format!("[out:json];{};out;", query))
+                .send()?;
+            let status = response.status();
+            if (status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::GATEWAY_TIMEOUT)
+                && retries < self.overpass_retries
+            {
+                retries += 1;
+                warn!(
+                    "Overpass said {}, retry #{} in {:?}",
+                    status, retries, delay
+                );
+                sleep(delay);
+                delay *= 2;
+                continue;
+            }
+            return response
+                .error_for_status()
+                .and_then(|mut r| r.json::<Value>());
+        }
+    }
+
+    fn classifier(&self) -> Arc<Classifier> {
+        self.places_config
+            .clone()
+            .unwrap_or_else(|| Arc::new(Classifier::default()))
+    }
+
+    /// Get the index of the local places file, loading it on first use.
+    fn local_index(&self) -> Result<Arc<PlaceIndex>, Error> {
+        let mut index = self.index.lock().unwrap();
+        if let Some(ref index) = *index {
+            return Ok(index.clone());
         }
-    } else {
-        warn!("Tag-less object {:?}", obj);
-        None
+        let path = self.places_file.as_ref().ok_or(Error::NoSource)?;
+        let loaded = PlaceIndex::load(path, &self.classifier())
+            .map_err(Error::Local)?;
+        let loaded = Arc::new(loaded);
+        *index = Some(loaded.clone());
+        Ok(loaded)
     }
 }
 
-fn tag_str<'a>(tags: &'a Value, name: &str) -> Option<&'a str> {
-    tags.get(name).and_then(Value::as_str)
+fn osm_id(obj: &Value) -> Option<i64> {
+    obj.get("id").and_then(Value::as_i64)
 }
 
 fn get_or_create_place(
@@ -300,6 +352,31 @@ fn get_or_create_place(
         })
 }
 
+/// Store the osm tags and preferred language names of a place.
+fn save_tags_and_names(
+    c: &PgConnection,
+    place: &Place,
+    area: &Area,
+) -> Result<(), DieselError> {
+    let tags = area.tags.to_string();
+    if place.osm_tags.as_ref() != Some(&tags) {
+        use crate::schema::places::dsl::*;
+        diesel::update(places.find(place.id))
+            .set(osm_tags.eq(&tags))
+            .execute(c)?;
+    }
+    use crate::schema::place_names::dsl::*;
+    for (tlang, tname) in &area.names {
+        diesel::insert_into(place_names)
+            .values((place_id.eq(place.id), lang.eq(tlang), name.eq(tname)))
+            .on_conflict((place_id, lang))
+            .do_update()
+            .set(name.eq(tname))
+            .execute(c)?;
+    }
+    Ok(())
+}
+
 fn is_duplicate<T>(r: &Result<T, DieselError>) -> bool {
     match r {
         Err(DieselError::DatabaseError(
@@ -315,4 +392,27 @@ pub enum Error {
     NoPosition(i32),
     Db(i32, diesel::result::Error),
     Server(i32, reqwest::Error),
+    Local(String),
+    NoSource,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoPosition(image) => {
+                write!(out, "No position for #{}", image)
+            }
+            Error::Db(image, e) => {
+                write!(out, "Database error for #{}: {}", image, e)
+            }
+            Error::Server(_, e) => write!(out, "Overpass error: {}", e),
+            Error::Local(e) => write!(out, "Local places error: {}", e),
+            Error::NoSource => {
+                write!(
+                    out,
+                    "Neither an overpass url nor a places file is given"
+                )
+            }
+        }
+    }
 }
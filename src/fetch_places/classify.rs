@@ -0,0 +1,243 @@
+//! Classification of osm areas as places with a level and names.
+//!
+//! The rules are read from a json file, see `levels.json` in this
+//! directory for the built-in default rules and format.  The rules
+//! are tried in order, and the first matching rule gives the level.
+use super::queue::RateLimiter;
+use super::{is_duplicate, save_tags_and_names, Area, OverpassOpt};
+use crate::adm::result::Error;
+use crate::models::Place;
+use crate::schema::places::dsl as l;
+use crate::DbOpt;
+use diesel::prelude::*;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Reclassify {
+    #[structopt(flatten)]
+    db: DbOpt,
+    #[structopt(flatten)]
+    overpass: OverpassOpt,
+    /// Fetch tags from overpass for places that has no stored tags.
+    #[structopt(long)]
+    fetch_missing: bool,
+    /// Max number of overpass requests per second for --fetch-missing.
+    #[structopt(long, default_value = "1")]
+    max_rate: f64,
+}
+
+impl Reclassify {
+    pub fn run(&self) -> Result<(), Error> {
+        let db = self.db.connect()?;
+        let classifier = self.overpass.classifier();
+        let limiter = RateLimiter::new(self.max_rate);
+        let places = l::places
+            .filter(l::osm_id.is_not_null())
+            .order(l::id)
+            .load::<Place>(&db)?;
+        let (mut changed, mut missing) = (0, 0);
+        for place in &places {
+            let osm_id = place.osm_id.unwrap_or_default();
+            let tags = match place.osm_tags {
+                Some(ref tags) => serde_json::from_str(tags).ok(),
+                None if self.fetch_missing => {
+                    limiter.wait();
+                    self.overpass.fetch_tags(osm_id)?
+                }
+                None => None,
+            };
+            let tags = match tags {
+                Some(tags) => tags,
+                None => {
+                    missing += 1;
+                    continue;
+                }
+            };
+            if let Some(area) = classifier.area(osm_id, &tags) {
+                if update_place(&db, place, &area)? {
+                    changed += 1;
+                }
+            } else {
+                warn!(
+                    "{} ({}) is no longer classified",
+                    place.place_name, osm_id
+                );
+            }
+        }
+        println!(
+            "Updated {} of {} places, {} lacks osm tags.",
+            changed,
+            places.len(),
+            missing,
+        );
+        if changed > 0 {
+            println!(
+                "Run fetchplaces --link-parents to update the hierarchy."
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Update level, name and alternative names of a place.
+///
+/// Return true if the level or name was changed.
+fn update_place(
+    db: &PgConnection,
+    place: &Place,
+    area: &Area,
+) -> Result<bool, Error> {
+    save_tags_and_names(db, place, area)?;
+    let mut changed = false;
+    if place.osm_level != Some(area.level) {
+        info!(
+            "Level of {} changed from {:?} to {}",
+            place.place_name, place.osm_level, area.level,
+        );
+        diesel::update(l::places.find(place.id))
+            .set(l::osm_level.eq(area.level))
+            .execute(db)?;
+        changed = true;
+    }
+    if place.place_name != area.name {
+        let result = diesel::update(l::places.find(place.id))
+            .set(l::place_name.eq(&area.name))
+            .execute(db);
+        if is_duplicate(&result) {
+            warn!("Name {:?} is used by another place", area.name);
+        } else {
+            info!("Renamed {} to {}", place.place_name, area.name);
+            result?;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Classifier {
+    /// Preferred languages for place names, most preferred first.
+    ///
+    /// The plain `name` tag is used if no preferred name exists.
+    languages: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Rule {
+    tag: String,
+    /// Use the (numeric) value of the tag as level.
+    #[serde(default)]
+    numeric: bool,
+    /// Level by tag value.  The value "*" matches any tag value.
+    #[serde(default)]
+    levels: HashMap<String, i16>,
+}
+
+impl Classifier {
+    /// Load a classifier from a json file.
+    pub fn load(path: &str) -> Result<Arc<Classifier>, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map(Arc::new)
+            .map_err(|e| format!("Bad places config {}: {}", path, e))
+    }
+
+    /// Classify an osm area by its tags.
+    pub fn area(&self, osm_id: i64, tags: &Value) -> Option<Area> {
+        let name = self
+            .languages
+            .iter()
+            .filter_map(|lang| tag_str(tags, &format!("name:{}", lang)))
+            .next()
+            .or_else(|| tag_str(tags, "name"))?;
+        let level = self.rules.iter().filter_map(|r| r.level(tags)).next()?;
+        debug!("{} is level {}", name, level);
+        Some(Area {
+            osm_id,
+            name: name.to_string(),
+            level,
+            names: self
+                .languages
+                .iter()
+                .filter_map(|lang| {
+                    let name = tag_str(tags, &format!("name:{}", lang))?;
+                    Some((lang.clone(), name.to_string()))
+                })
+                .collect(),
+            tags: tags.clone(),
+        })
+    }
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        serde_json::from_str(include_str!("levels.json"))
+            .expect("Built-in place levels should be valid")
+    }
+}
+
+impl Rule {
+    fn level(&self, tags: &Value) -> Option<i16> {
+        let value = tag_str(tags, &self.tag)?;
+        if self.numeric {
+            value.parse().ok()
+        } else {
+            self.levels
+                .get(value)
+                .or_else(|| self.levels.get("*"))
+                .cloned()
+        }
+    }
+}
+
+fn tag_str<'a>(tags: &'a Value, name: &str) -> Option<&'a str> {
+    tags.get(name).and_then(Value::as_str)
+}
+
+#[test]
+fn default_rules_park() {
+    let tags = serde_json::json!({
+        "leisure": "park",
+        "name": "Humlegården",
+        "name:en": "Humlegarden",
+    });
+    let area = Classifier::default().area(17, &tags).unwrap();
+    assert_eq!((area.name.as_ref(), area.level), ("Humlegården", 14));
+}
+
+#[test]
+fn admin_level_and_language() {
+    let tags = serde_json::json!({
+        "admin_level": "2",
+        "building": "yes",
+        "name": "Sverige",
+        "name:en": "Sweden",
+        "name:sv": "Sverige",
+    });
+    let classifier = Classifier {
+        languages: vec!["en".into(), "sv".into()],
+        ..Classifier::default()
+    };
+    let area = classifier.area(17, &tags).unwrap();
+    assert_eq!((area.name.as_ref(), area.level), ("Sweden", 2));
+    assert_eq!(area.names.len(), 2);
+}
+
+#[test]
+fn any_building() {
+    let tags = serde_json::json!({"building": "yes", "name": "Hus"});
+    assert_eq!(
+        Classifier::default().area(17, &tags).map(|a| a.level),
+        Some(20)
+    );
+}
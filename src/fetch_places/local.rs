@@ -0,0 +1,247 @@
+//! Find places from a local file, rather than asking overpass.
+//!
+//! The file should be GeoJSON, as exported from osm data by e.g.
+//! `osmium export` or `osmtogeojson`.  Only polygons and
+//! multipolygons are used, and they are classified by the same tags
+//! as the results from overpass.
+use super::{Area, Classifier};
+use crate::models::Coord;
+use log::{debug, info};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// A polygon as a list of rings of (lat, lng) points.
+///
+/// The first ring is the outer boundary, any other rings are holes.
+type Polygon = Vec<Vec<(f64, f64)>>;
+
+/// An in-memory spatial index of areas.
+pub struct PlaceIndex {
+    areas: Vec<IndexedArea>,
+    /// Indexes into areas, by whole degree (lat, lng) cells.
+    grid: HashMap<(i16, i16), Vec<usize>>,
+}
+
+impl fmt::Debug for PlaceIndex {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "PlaceIndex({} areas)", self.areas.len())
+    }
+}
+
+struct IndexedArea {
+    area: Area,
+    polygons: Vec<Polygon>,
+}
+
+impl PlaceIndex {
+    pub fn load(
+        path: &Path,
+        classifier: &Classifier,
+    ) -> Result<PlaceIndex, String> {
+        let file = File::open(path).map_err(|e| {
+            format!("Failed to open {}: {}", path.display(), e)
+        })?;
+        let data: Value = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Bad json in {}: {}", path.display(), e))?;
+        let features = data
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| format!("No features in {}", path.display()))?;
+        let mut index = PlaceIndex {
+            areas: Vec::new(),
+            grid: HashMap::new(),
+        };
+        for feature in features {
+            if let Some(area) = IndexedArea::from_feature(feature, classifier)
+            {
+                index.add(area);
+            }
+        }
+        info!("Loaded {} areas from {}", index.areas.len(), path.display());
+        Ok(index)
+    }
+
+    fn add(&mut self, area: IndexedArea) {
+        let i = self.areas.len();
+        let (min, max) = area.bbox();
+        for lat in cell(min.0)..=cell(max.0) {
+            for lng in cell(min.1)..=cell(max.1) {
+                self.grid.entry((lat, lng)).or_default().push(i);
+            }
+        }
+        self.areas.push(area);
+    }
+
+    /// All areas containing a given position.
+    pub fn lookup(&self, pos: &Coord) -> Vec<Area> {
+        let point = (pos.x, pos.y);
+        self.grid
+            .get(&(cell(pos.x), cell(pos.y)))
+            .map(|candidates| {
+                candidates
+                    .iter()
+                    .map(|i| &self.areas[*i])
+                    .filter(|a| a.contains(point))
+                    .map(|a| a.area.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl IndexedArea {
+    fn from_feature(
+        feature: &Value,
+        classifier: &Classifier,
+    ) -> Option<IndexedArea> {
+        let props = feature.get("properties")?;
+        let tags =
+            props.get("tags").filter(|t| t.is_object()).unwrap_or(props);
+        let osm_id = feature
+            .get("id")
+            .or_else(|| props.get("@id"))
+            .or_else(|| props.get("id"))
+            .and_then(parse_osm_id)?;
+        let area = classifier.area(osm_id, tags)?;
+        let geometry = feature.get("geometry")?;
+        let coordinates = geometry.get("coordinates")?;
+        let polygons = match geometry.get("type").and_then(Value::as_str) {
+            Some("Polygon") => vec![polygon(coordinates)?],
+            Some("MultiPolygon") => coordinates
+                .as_array()?
+                .iter()
+                .map(polygon)
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        debug!("Area {}: {} (level {})", osm_id, area.name, area.level);
+        Some(IndexedArea { area, polygons })
+    }
+
+    fn bbox(&self) -> ((f64, f64), (f64, f64)) {
+        let points = self.polygons.iter().flat_map(|p| p[0].iter());
+        points.fold(
+            ((90., 180.), (-90., -180.)),
+            |((lat0, lng0), (lat1, lng1)), &(lat, lng)| {
+                (
+                    (lat0.min(lat), lng0.min(lng)),
+                    (lat1.max(lat), lng1.max(lng)),
+                )
+            },
+        )
+    }
+
+    fn contains(&self, point: (f64, f64)) -> bool {
+        self.polygons.iter().any(|p| {
+            in_ring(&p[0], point) && !p[1..].iter().any(|h| in_ring(h, point))
+        })
+    }
+}
+
+fn cell(degrees: f64) -> i16 {
+    degrees.floor() as i16
+}
+
+/// Parse a GeoJSON polygon, converting [lng, lat] to (lat, lng).
+fn polygon(coordinates: &Value) -> Option<Polygon> {
+    let rings = coordinates
+        .as_array()?
+        .iter()
+        .map(|ring| {
+            ring.as_array()?
+                .iter()
+                .map(|p| Some((p.get(1)?.as_f64()?, p.get(0)?.as_f64()?)))
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if rings.is_empty() {
+        None
+    } else {
+        Some(rings)
+    }
+}
+
+/// Check if a point is inside a ring, by ray casting.
+fn in_ring(ring: &[(f64, f64)], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for (i, &(xi, yi)) in ring.iter().enumerate() {
+        let (xj, yj) = ring[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Get an osm id in the form used for areas by overpass.
+///
+/// Overpass area ids are the way id plus 2400000000 or the relation
+/// id plus 3600000000.  The formats used by osmtogeojson
+/// ("relation/4711"), osmium ("r4711", or "a9423" for areas) and
+/// plain numbers (assumed to already be area ids) are supported.
+fn parse_osm_id(id: &Value) -> Option<i64> {
+    if let Some(id) = id.as_i64() {
+        return Some(id);
+    }
+    let id = id.as_str()?;
+    let (kind, num) = if let Some(n) = id.strip_prefix("relation/") {
+        ('r', n)
+    } else if let Some(n) = id.strip_prefix("way/") {
+        ('w', n)
+    } else {
+        let mut chars = id.chars();
+        (chars.next()?, chars.as_str())
+    };
+    let num = num.parse::<i64>().ok()?;
+    match kind {
+        'r' => Some(3_600_000_000 + num),
+        'w' => Some(2_400_000_000 + num),
+        // osmium area ids are twice the way id, or twice the relation
+        // id plus one.
+        'a' if num % 2 == 0 => Some(2_400_000_000 + num / 2),
+        'a' => Some(3_600_000_000 + num / 2),
+        _ => None,
+    }
+}
+
+#[test]
+fn osm_id_relation() {
+    let id = Value::from("relation/52822");
+    assert_eq!(parse_osm_id(&id), Some(3_600_052_822));
+}
+
+#[test]
+fn osm_id_osmium_way() {
+    assert_eq!(parse_osm_id(&Value::from("w17")), Some(2_400_000_017));
+}
+
+#[test]
+fn osm_id_osmium_area() {
+    assert_eq!(parse_osm_id(&Value::from("a105645")), Some(3_600_052_822));
+}
+
+#[test]
+fn point_in_ring_with_hole() {
+    let area = IndexedArea {
+        area: Area {
+            osm_id: 1,
+            name: "Test".into(),
+            level: 8,
+            names: vec![],
+            tags: Value::Null,
+        },
+        polygons: vec![vec![
+            vec![(0., 0.), (0., 4.), (4., 4.), (4., 0.), (0., 0.)],
+            vec![(1., 1.), (1., 2.), (2., 2.), (2., 1.), (1., 1.)],
+        ]],
+    };
+    assert!(area.contains((3., 3.)));
+    assert!(!area.contains((1.5, 1.5)));
+    assert!(!area.contains((5., 1.)));
+}
@@ -0,0 +1,289 @@
+//! Fetch places for many photos, using a queue stored in the database.
+//!
+//! Photos close to each other are clustered, so one lookup serves
+//! all photos in a cluster.  Lookups are done by a configurable
+//! number of worker threads, with a limited request rate.
+use super::{Area, Error, OverpassOpt};
+use crate::adm::result::Error as AdmError;
+use crate::models::Coord;
+use crate::schema::photo_places::dsl as pl;
+use crate::schema::place_queue::dsl as q;
+use crate::schema::positions::dsl as pos;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{delete, insert_into, update};
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Add a photo to the queue of photos needing places.
+///
+/// This is done when the position of a photo is changed, so a photo
+/// already in the queue is looked up again even if an earlier lookup
+/// found no places.
+pub fn enqueue(
+    db: &PgConnection,
+    photo: i32,
+) -> Result<(), diesel::result::Error> {
+    insert_into(q::place_queue)
+        .values(q::photo_id.eq(photo))
+        .on_conflict(q::photo_id)
+        .do_update()
+        .set(q::no_places.eq(None::<NaiveDateTime>))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Add all photos that has a position but no places to the queue.
+///
+/// Photos already in the queue are left as they are, so photos where
+/// a lookup found no places are not looked up again.
+pub fn enqueue_missing(db: &PgConnection) -> Result<usize, AdmError> {
+    let missing = pos::positions
+        .select(pos::photo_id)
+        .filter(pos::photo_id.ne_all(pl::photo_places.select(pl::photo_id)))
+        .load::<i32>(db)?;
+    let rows = missing
+        .iter()
+        .map(|photo| q::photo_id.eq(photo))
+        .collect::<Vec<_>>();
+    let mut n = 0;
+    for chunk in rows.chunks(10_000) {
+        n += insert_into(q::place_queue)
+            .values(chunk)
+            .on_conflict_do_nothing()
+            .execute(db)?;
+    }
+    Ok(n)
+}
+
+pub struct QueueOpt {
+    /// Max number of queued photos to handle, or None for all.
+    pub limit: Option<i64>,
+    /// Photos closer than this (in meters) share a lookup.
+    pub cluster_distance: u32,
+    /// Max number of lookups per second.
+    pub max_rate: f64,
+    /// Number of lookups to run in parallel.
+    pub concurrency: usize,
+}
+
+/// A group of nearby photos.
+struct Cluster {
+    coord: Coord,
+    photos: Vec<i32>,
+}
+
+/// Result of running the queue.
+#[derive(Default)]
+pub struct Summary {
+    pub lookups: usize,
+    pub photos: usize,
+    /// Number of photos where no places was found.
+    pub no_places: usize,
+    pub failures: BTreeMap<String, Vec<i32>>,
+}
+
+impl Summary {
+    pub fn print(&self) {
+        println!(
+            "Found places for {} photos in {} lookups.",
+            self.photos, self.lookups,
+        );
+        if self.no_places > 0 {
+            println!("Found no places for {} photos.", self.no_places);
+        }
+        for (error, photos) in &self.failures {
+            println!("Failed for {} photos: {}", photos.len(), error);
+            debug!("Failed photos: {:?}", photos);
+        }
+    }
+}
+
+/// Fetch places for queued photos.
+pub fn run(
+    db: &PgConnection,
+    overpass: &OverpassOpt,
+    opt: &QueueOpt,
+) -> Result<Summary, AdmError> {
+    let clusters = load_clusters(db, opt)?;
+    info!("Fetching places for {} clusters", clusters.len());
+    let clusters = Arc::new(Mutex::new(clusters.into_iter()));
+    let limiter = Arc::new(RateLimiter::new(opt.max_rate));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..opt.concurrency.max(1) {
+        let (clusters, limiter, tx) =
+            (clusters.clone(), limiter.clone(), tx.clone());
+        let overpass = overpass.clone();
+        thread::spawn(move || loop {
+            let next = clusters.lock().unwrap().next();
+            if let Some(cluster) = next {
+                limiter.wait();
+                let first = cluster.photos[0];
+                let result = overpass.areas_at(first, &cluster.coord);
+                if tx.send((cluster, result)).is_err() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        });
+    }
+    drop(tx);
+
+    let mut summary = Summary::default();
+    for (cluster, result) in rx {
+        summary.lookups += 1;
+        match result.and_then(|areas| store(db, overpass, &cluster, &areas)) {
+            Ok(true) => summary.photos += cluster.photos.len(),
+            Ok(false) => summary.no_places += cluster.photos.len(),
+            Err(e) => {
+                warn!("Failed to get places for {:?}: {}", cluster.photos, e);
+                record_failure(db, &cluster, &e)?;
+                summary
+                    .failures
+                    .entry(e.to_string())
+                    .or_default()
+                    .extend(&cluster.photos);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn load_clusters(
+    db: &PgConnection,
+    opt: &QueueOpt,
+) -> Result<Vec<Cluster>, AdmError> {
+    let queued = q::place_queue
+        .select(q::photo_id)
+        .filter(q::no_places.is_null())
+        .order((q::attempts, q::photo_id.desc()))
+        .into_boxed();
+    let queued = if let Some(limit) = opt.limit {
+        queued.limit(limit)
+    } else {
+        queued
+    };
+    let queued = queued.load::<i32>(db)?;
+    let positions = pos::positions
+        .select((pos::photo_id, pos::latitude, pos::longitude))
+        .filter(pos::photo_id.eq_any(&queued))
+        .order(pos::photo_id.desc())
+        .load::<(i32, i32, i32)>(db)?;
+    Ok(cluster(&positions, opt.cluster_distance))
+}
+
+/// Group photos by position, in cells of about `distance` meters.
+///
+/// Positions are in microdegrees, and a microdegree of latitude is
+/// about 0.11 meters.  The cells gets narrower far from equator,
+/// which means smaller clusters, which is fine.
+fn cluster(positions: &[(i32, i32, i32)], distance: u32) -> Vec<Cluster> {
+    let size = (distance.max(1) * 9) as i32;
+    let mut result: Vec<Cluster> = Vec::new();
+    let mut cells = HashMap::new();
+    for &(photo, lat, lng) in positions {
+        let cell = (lat.div_euclid(size), lng.div_euclid(size));
+        let i = *cells.entry(cell).or_insert_with(|| {
+            result.push(Cluster {
+                coord: (lat, lng).into(),
+                photos: vec![],
+            });
+            result.len() - 1
+        });
+        result[i].photos.push(photo);
+    }
+    result
+}
+
+/// Store the places found for a cluster.
+///
+/// The photos are removed from the queue, unless no places was found.
+/// Then they are kept, marked as having no places, so they are not
+/// queued again.  Return true if any places was found.
+fn store(
+    db: &PgConnection,
+    overpass: &OverpassOpt,
+    cluster: &Cluster,
+    areas: &[Area],
+) -> Result<bool, Error> {
+    for photo in &cluster.photos {
+        let queued = q::place_queue.find(photo);
+        if areas.is_empty() {
+            update(queued)
+                .set(q::no_places.eq(diesel::dsl::now))
+                .execute(db)
+                .map_err(|e| Error::Db(*photo, e))?;
+        } else {
+            overpass.store_areas(db, *photo, areas)?;
+            delete(queued)
+                .execute(db)
+                .map_err(|e| Error::Db(*photo, e))?;
+        }
+    }
+    Ok(!areas.is_empty())
+}
+
+fn record_failure(
+    db: &PgConnection,
+    cluster: &Cluster,
+    error: &Error,
+) -> Result<(), AdmError> {
+    update(q::place_queue.filter(q::photo_id.eq_any(&cluster.photos)))
+        .set((
+            q::attempts.eq(q::attempts + 1),
+            q::last_error.eq(error.to_string()),
+        ))
+        .execute(db)?;
+    Ok(())
+}
+
+/// Make sure requests are not started more often than a given rate.
+pub(super) struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(super) fn new(max_rate: f64) -> Self {
+        RateLimiter {
+            interval: Duration::from_secs_f64(1. / max_rate.max(0.001)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+    /// Wait until the next request may be started.
+    pub(super) fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        let now = Instant::now();
+        if start > now {
+            thread::sleep(start - now);
+        }
+    }
+}
+
+#[test]
+fn cluster_nearby() {
+    let positions = [
+        (1, 59_330_000, 18_060_000),
+        (2, 59_330_100, 18_060_100),
+        (3, 59_340_000, 18_060_000),
+        (4, 59_330_200, 18_060_050),
+    ];
+    let clusters = cluster(&positions, 100);
+    assert_eq!(
+        clusters
+            .iter()
+            .map(|c| c.photos.clone())
+            .collect::<Vec<_>>(),
+        vec![vec![1, 2, 4], vec![3]],
+    );
+}
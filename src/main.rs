@@ -11,10 +11,13 @@ mod photosdir;
 mod pidfiles;
 mod schema;
 mod server;
+mod xmp;
 
 use crate::adm::result::Error;
 use crate::adm::stats::show_stats;
-use crate::adm::{findphotos, makepublic, precache, storestatics, users};
+use crate::adm::{
+    clock_shift, findphotos, geotag, makepublic, precache, storestatics, users,
+};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenv::dotenv;
@@ -34,12 +37,23 @@ enum RPhotos {
     Fetchplaces(fetch_places::Fetchplaces),
     /// Find new photos in the photo directory
     Findphotos(findphotos::Findphotos),
+    /// Set positions of photos from gpx track files.
+    ///
+    /// Photos are matched by time, and positions are interpolated
+    /// between the track points.
+    Geotag(geotag::Geotag),
     /// Make sure the photos has thumbnails stored in cache.
     ///
     /// The time limit is checked after each stored image, so the
     /// command will complete in slightly more than the max time and
     /// one image will be processed even if the max time is zero.
     Precache(precache::Args),
+    /// Shift the date of photos from a camera with a wrong clock.
+    ///
+    /// Without --apply, only list the photos that would be shifted.
+    Shiftclock(clock_shift::Shiftclock),
+    /// Classify stored places again, after changing the place rules.
+    Reclassify(fetch_places::Reclassify),
     /// Show some statistics from the database
     Stats(DbOpt),
     /// Store statics as files for a web server
@@ -113,12 +127,15 @@ fn main() {
 fn run(args: &RPhotos) -> Result<(), Error> {
     match args {
         RPhotos::Findphotos(cmd) => cmd.run(),
+        RPhotos::Geotag(cmd) => cmd.run(),
         RPhotos::Makepublic(cmd) => cmd.run(),
         RPhotos::Stats(db) => show_stats(&db.connect()?),
         RPhotos::Userlist { db } => users::list(&db.connect()?),
         RPhotos::Userpass { db, user } => users::passwd(&db.connect()?, user),
         RPhotos::Fetchplaces(cmd) => cmd.run(),
         RPhotos::Precache(cmd) => cmd.run(),
+        RPhotos::Reclassify(cmd) => cmd.run(),
+        RPhotos::Shiftclock(cmd) => cmd.run(),
         RPhotos::Storestatics { dir } => storestatics::to_dir(dir),
         RPhotos::Runserver(ra) => server::run(ra),
     }
@@ -1,6 +1,12 @@
+use crate::adm::result::Error as AdmError;
 use crate::schema::attributions::dsl as a;
 use crate::schema::cameras;
 use crate::schema::cameras::dsl as c;
+use crate::schema::clock_shift_photos::dsl as csp;
+use crate::schema::comments::dsl as cm;
+use crate::schema::favourites::dsl as fav;
+use crate::schema::lenses;
+use crate::schema::lenses::dsl as le;
 use crate::schema::people::dsl as h;
 use crate::schema::photo_people::dsl as ph;
 use crate::schema::photo_places::dsl as pl;
@@ -8,17 +14,24 @@ use crate::schema::photo_tags::dsl as pt;
 use crate::schema::photos;
 use crate::schema::photos::dsl as p;
 use crate::schema::places::dsl as l;
+use crate::schema::portraits::dsl as po;
 use crate::schema::positions::dsl as pos;
+use crate::schema::ratings::dsl as r;
 use crate::schema::tags::dsl as t;
+use crate::schema::users::dsl as u;
 use chrono::naive::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use diesel;
+use diesel::dsl::{exists, sql};
+use diesel::expression::SqlLiteral;
 use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 use diesel::result::Error;
-use diesel::sql_types::Integer;
-use log::error;
+use diesel::sql_types::{Integer, Nullable, Timestamptz};
+use log::{debug, error};
 use slug::slugify;
 use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 
 #[derive(AsChangeset, Clone, Debug, Identifiable, Queryable)]
 pub struct Photo {
@@ -32,6 +45,25 @@ pub struct Photo {
     pub attribution_id: Option<i32>,
     pub width: i32,
     pub height: i32,
+    pub lens_id: Option<i32>,
+    pub exposure_time: Option<f32>,
+    pub aperture: Option<f32>,
+    pub iso: Option<i32>,
+    pub focal_length: Option<f32>,
+    pub focal_length_35: Option<i16>,
+    pub flash: Option<bool>,
+    pub altitude: Option<f32>,
+    pub direction: Option<f32>,
+    pub utc_date: Option<DateTime<Utc>>,
+    pub date_manual: bool,
+    /// When the photo was added to the database.
+    pub created: NaiveDateTime,
+    /// When the photo (or its tags, people or places) was last changed.
+    pub updated: NaiveDateTime,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// True if the caption is set by hand.
+    pub caption_manual: bool,
 }
 
 #[derive(Debug)]
@@ -51,6 +83,14 @@ impl Photo {
         format!("rp{}{:?}", self.id, size)
     }
 
+    /// Cache key for a scaled region of this photo.
+    pub fn region_cache_key(&self, region: &Region, size: SizeTag) -> String {
+        format!(
+            "rp{}{:?}r{:.4}_{:.4}_{:.4}_{:.4}",
+            self.id, size, region.x, region.y, region.w, region.h,
+        )
+    }
+
     #[allow(dead_code)]
     pub fn query<'a>(auth: bool) -> photos::BoxedQuery<'a, Pg> {
         let result = p::photos
@@ -64,6 +104,23 @@ impl Photo {
         }
     }
 
+    /// The instant a photo was taken, as used for ordering by time.
+    ///
+    /// Photos with unknown time zone are treated as if they were
+    /// taken in utc.
+    pub fn instant_expr() -> SqlLiteral<Nullable<Timestamptz>> {
+        sql("coalesce(utc_date, date at time zone 'UTC')")
+    }
+
+    /// The instant a photo was taken, as seconds since the epoch.
+    ///
+    /// This is the same ordering as `instant_expr`.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.utc_date
+            .map(|d| d.timestamp())
+            .or_else(|| self.date.map(|d| d.timestamp()))
+    }
+
     pub fn update_by_path(
         db: &PgConnection,
         file_path: &str,
@@ -86,10 +143,14 @@ impl Photo {
                     .get_result::<Photo>(db)?;
             }
             if exifdate.is_some() && exifdate != pic.date {
-                change = true;
-                pic = diesel::update(p::photos.find(pic.id))
-                    .set(p::date.eq(exifdate))
-                    .get_result::<Photo>(db)?;
+                if pic.has_corrected_date(db)? {
+                    debug!("Keeping corrected date for #{}", pic.id);
+                } else {
+                    change = true;
+                    pic = diesel::update(p::photos.find(pic.id))
+                        .set(p::date.eq(exifdate))
+                        .get_result::<Photo>(db)?;
+                }
             }
             if let Some(ref camera) = *camera {
                 if pic.camera_id != Some(camera.id) {
@@ -137,6 +198,105 @@ impl Photo {
         }
     }
 
+    /// True if the date of this photo has been set by hand or
+    /// corrected by a clock shift, and should not be overwritten by
+    /// the date from exif.
+    pub fn has_corrected_date(
+        &self,
+        db: &PgConnection,
+    ) -> Result<bool, Error> {
+        if self.date_manual {
+            return Ok(true);
+        }
+        diesel::select(exists(
+            csp::clock_shift_photos.filter(csp::photo_id.eq(self.id)),
+        ))
+        .get_result(db)
+    }
+
+    /// Set the date of some photos by hand.
+    ///
+    /// The time zone of a manually set date is unknown, so the utc
+    /// date is cleared.
+    pub fn set_manual_date(
+        db: &PgConnection,
+        ids: &[i32],
+        date: NaiveDateTime,
+    ) -> Result<usize, Error> {
+        diesel::update(p::photos.filter(p::id.eq_any(ids)))
+            .set((
+                p::date.eq(date),
+                p::utc_date.eq(None::<DateTime<Utc>>),
+                p::date_manual.eq(true),
+            ))
+            .execute(db)
+    }
+
+    /// Set the title and description of a photo by hand.
+    ///
+    /// Blank values are stored as null.  The caption is marked as
+    /// manual, so it is not imported from the image file again.
+    pub fn set_caption(
+        db: &PgConnection,
+        id: i32,
+        title: &str,
+        description: &str,
+    ) -> Result<Photo, Error> {
+        let (title, description) = (title.trim(), description.trim());
+        diesel::update(p::photos.find(id))
+            .set((
+                p::title.eq(Some(title).filter(|s| !s.is_empty())),
+                p::description.eq(Some(description).filter(|s| !s.is_empty())),
+                p::caption_manual.eq(true),
+            ))
+            .get_result(db)
+    }
+
+    /// Set the title and description from the image file, unless
+    /// they are already set or the caption is set by hand.
+    ///
+    /// Return true if anything was changed.
+    pub fn import_caption(
+        &self,
+        db: &PgConnection,
+        title: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<bool, Error> {
+        if self.caption_manual {
+            return Ok(false);
+        }
+        let title = self.title.as_deref().or(title);
+        let description = self.description.as_deref().or(description);
+        if title == self.title.as_deref()
+            && description == self.description.as_deref()
+        {
+            return Ok(false);
+        }
+        diesel::update(p::photos.find(self.id))
+            .set((p::title.eq(title), p::description.eq(description)))
+            .execute(db)?;
+        Ok(true)
+    }
+
+    /// The closest photo by path that has a date.
+    ///
+    /// Photos without a date is often scanned or otherwise
+    /// numbered in order, so the path is a good hint for the date.
+    pub fn dated_neighbour(
+        &self,
+        db: &PgConnection,
+        before: bool,
+    ) -> Result<Option<Photo>, Error> {
+        let q = p::photos.filter(p::date.is_not_null()).into_boxed();
+        if before {
+            q.filter(p::path.lt(&self.path)).order(p::path.desc())
+        } else {
+            q.filter(p::path.gt(&self.path)).order(p::path.asc())
+        }
+        .first(db)
+        .optional()
+    }
+
     pub fn load_people(
         &self,
         db: &PgConnection,
@@ -176,6 +336,153 @@ impl Photo {
             .load(db)
     }
 
+    /// The people in this photo that has a known region.
+    pub fn load_regions(
+        &self,
+        db: &PgConnection,
+    ) -> Result<Vec<(Person, Region)>, Error> {
+        Ok(ph::photo_people
+            .inner_join(h::people)
+            .filter(ph::photo_id.eq(self.id))
+            .select((h::people::all_columns(), REGION_COLUMNS))
+            .load::<(Person, RegionColumns)>(db)?
+            .into_iter()
+            .filter_map(|(person, r)| Some((person, Region::from_columns(r)?)))
+            .collect())
+    }
+
+    /// Set (or with `None`, clear) where a person is in a photo.
+    ///
+    /// The person is added to the photo if not already there.
+    pub fn set_region(
+        db: &PgConnection,
+        photo: i32,
+        person: i32,
+        region: Option<Region>,
+    ) -> Result<(), Error> {
+        let values = (
+            ph::region_x.eq(region.map(|r| r.x)),
+            ph::region_y.eq(region.map(|r| r.y)),
+            ph::region_w.eq(region.map(|r| r.w)),
+            ph::region_h.eq(region.map(|r| r.h)),
+        );
+        let updated = diesel::update(
+            ph::photo_people
+                .filter(ph::photo_id.eq(photo))
+                .filter(ph::person_id.eq(person)),
+        )
+        .set(values)
+        .execute(db)?;
+        if updated == 0 {
+            diesel::insert_into(ph::photo_people)
+                .values((ph::photo_id.eq(photo), ph::person_id.eq(person)))
+                .execute(db)?;
+            return Self::set_region(db, photo, person, region);
+        }
+        Ok(())
+    }
+
+    /// The names of the users that has this photo as a favourite.
+    pub fn load_favourited_by(
+        &self,
+        db: &PgConnection,
+    ) -> Result<Vec<String>, Error> {
+        fav::favourites
+            .inner_join(u::users)
+            .filter(fav::photo_id.eq(self.id))
+            .order(fav::created)
+            .select(u::username)
+            .load(db)
+    }
+
+    /// Add or remove a photo as a favourite of a user.
+    ///
+    /// Return true if the photo is now a favourite.
+    pub fn toggle_favourite(
+        db: &PgConnection,
+        photo: i32,
+        user: &str,
+    ) -> Result<bool, Error> {
+        let user = user_id(db, user)?;
+        let removed =
+            diesel::delete(fav::favourites.find((photo, user))).execute(db)?;
+        if removed == 0 {
+            diesel::insert_into(fav::favourites)
+                .values((fav::photo_id.eq(photo), fav::user_id.eq(user)))
+                .execute(db)?;
+        }
+        Ok(removed == 0)
+    }
+
+    /// The personal rating of this photo by a user.
+    pub fn load_rating(
+        &self,
+        db: &PgConnection,
+        user: &str,
+    ) -> Result<Option<i16>, Error> {
+        r::ratings
+            .inner_join(u::users)
+            .filter(r::photo_id.eq(self.id))
+            .filter(u::username.eq(user))
+            .select(r::grade)
+            .first(db)
+            .optional()
+    }
+
+    /// Set (or with `None`, remove) the personal rating of a photo.
+    pub fn set_rating(
+        db: &PgConnection,
+        photo: i32,
+        user: &str,
+        grade: Option<i16>,
+    ) -> Result<(), Error> {
+        let user = user_id(db, user)?;
+        if let Some(grade) = grade {
+            diesel::insert_into(r::ratings)
+                .values((
+                    r::photo_id.eq(photo),
+                    r::user_id.eq(user),
+                    r::grade.eq(grade),
+                ))
+                .on_conflict((r::photo_id, r::user_id))
+                .do_update()
+                .set((r::grade.eq(grade), r::updated.eq(diesel::dsl::now)))
+                .execute(db)?;
+        } else {
+            diesel::delete(r::ratings.find((photo, user))).execute(db)?;
+        }
+        Ok(())
+    }
+
+    /// Use the ratings of a user rather than the global grade of
+    /// the photos, if the user has chosen so.
+    ///
+    /// This affects how groups are split and which photo is shown
+    /// for each group.
+    pub fn apply_own_ratings(
+        photos: &mut [Photo],
+        db: &PgConnection,
+        user: &str,
+    ) -> Result<(), Error> {
+        if !uses_own_ratings(db, user)? {
+            return Ok(());
+        }
+        let ratings = r::ratings
+            .inner_join(u::users)
+            .filter(u::username.eq(user))
+            .filter(r::photo_id.eq_any(photos.iter().map(|p| p.id)))
+            .select((r::photo_id, r::grade))
+            .load::<(i32, i16)>(db)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        for photo in photos {
+            if let Some(grade) = ratings.get(&photo.id) {
+                photo.grade = Some(*grade);
+            }
+        }
+        Ok(())
+    }
+
     pub fn load_position(&self, db: &PgConnection) -> Option<Coord> {
         match pos::positions
             .filter(pos::photo_id.eq(self.id))
@@ -199,6 +506,34 @@ impl Photo {
         self.camera_id
             .and_then(|i| c::cameras.find(i).first(db).ok())
     }
+    pub fn load_lens(&self, db: &PgConnection) -> Option<Lens> {
+        self.lens_id.and_then(|i| le::lenses.find(i).first(db).ok())
+    }
+    /// A human-readable summary of the exposure, if known.
+    ///
+    /// Something like "1/250 s, f/2.8, ISO 400, 50 mm (75 mm in 35mm)".
+    pub fn exposure_summary(&self) -> Option<String> {
+        let parts = [
+            self.exposure_time.map(format_exposure_time),
+            self.aperture.map(|a| format!("f/{:.1}", a)),
+            self.iso.map(|iso| format!("ISO {}", iso)),
+            match (self.focal_length, self.focal_length_35) {
+                (Some(l), Some(l35)) => {
+                    Some(format!("{:.0} mm ({} mm in 35mm)", l, l35))
+                }
+                (Some(l), None) => Some(format!("{:.0} mm", l)),
+                (None, Some(l35)) => Some(format!("{} mm in 35mm", l35)),
+                (None, None) => None,
+            },
+            self.flash.filter(|f| *f).map(|_| "flash".to_string()),
+        ];
+        let parts = parts.iter().flatten().cloned().collect::<Vec<_>>();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(", "))
+        }
+    }
     pub fn get_size(&self, size: SizeTag) -> (u32, u32) {
         let (width, height) = (self.width, self.height);
         let scale = f64::from(size.px()) / f64::from(max(width, height));
@@ -228,6 +563,22 @@ impl Photo {
             attribution_id: None,
             width: 4000,
             height: 3000,
+            lens_id: None,
+            exposure_time: None,
+            aperture: None,
+            iso: None,
+            focal_length: None,
+            focal_length_35: None,
+            flash: None,
+            altitude: None,
+            direction: None,
+            utc_date: None,
+            date_manual: false,
+            created: NaiveDate::from_ymd(2019, 1, 1).and_hms(0, 0, 0),
+            updated: NaiveDate::from_ymd(2019, 1, 1).and_hms(0, 0, 0),
+            title: None,
+            description: None,
+            caption_manual: false,
         }
     }
 }
@@ -236,6 +587,10 @@ pub trait Facet {
     fn by_slug(slug: &str, db: &PgConnection) -> Result<Self, Error>
     where
         Self: Sized;
+    fn id(&self) -> i32;
+    fn slug(&self) -> &str;
+    /// The name to show for this item.
+    fn name(&self) -> &str;
 }
 
 #[derive(Debug, Clone, Queryable)]
@@ -249,6 +604,15 @@ impl Facet for Tag {
     fn by_slug(slug: &str, db: &PgConnection) -> Result<Tag, Error> {
         t::tags.filter(t::slug.eq(slug)).first(db)
     }
+    fn id(&self) -> i32 {
+        self.id
+    }
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+    fn name(&self) -> &str {
+        &self.tag_name
+    }
 }
 
 #[derive(Debug, Clone, Queryable)]
@@ -288,6 +652,134 @@ impl Facet for Person {
     fn by_slug(slug: &str, db: &PgConnection) -> Result<Person, Error> {
         h::people.filter(h::slug.eq(slug)).first(db)
     }
+    fn id(&self) -> i32 {
+        self.id
+    }
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+    fn name(&self) -> &str {
+        &self.person_name
+    }
+}
+
+impl Person {
+    /// A photo showing the face of this person, and where in the
+    /// photo the face is.
+    ///
+    /// The best graded photo visible to the user is used.
+    pub fn face(
+        &self,
+        db: &PgConnection,
+        auth: bool,
+    ) -> Result<Option<(Photo, Region)>, Error> {
+        let found = Photo::query(auth)
+            .inner_join(ph::photo_people)
+            .filter(ph::person_id.eq(self.id))
+            .filter(ph::region_x.is_not_null())
+            .order((p::grade.desc().nulls_last(), p::date.desc().nulls_last()))
+            .select((p::photos::all_columns(), REGION_COLUMNS))
+            .first::<(Photo, RegionColumns)>(db)
+            .optional()?;
+        Ok(found
+            .and_then(|(photo, r)| Some((photo, Region::from_columns(r)?))))
+    }
+
+    /// The portrait of this person, if there is one visible to the user.
+    ///
+    /// A chosen portrait is used if visible, otherwise the best face
+    /// found by `face`.
+    pub fn portrait(
+        &self,
+        db: &PgConnection,
+        auth: bool,
+    ) -> Result<Option<Portrait>, Error> {
+        let chosen = Photo::query(auth)
+            .inner_join(po::portraits)
+            .filter(po::person_id.eq(self.id))
+            .select((
+                p::photos::all_columns(),
+                (po::region_x, po::region_y, po::region_w, po::region_h),
+            ))
+            .first::<(Photo, (f32, f32, f32, f32))>(db)
+            .optional()?;
+        if let Some((photo, (x, y, w, h))) = chosen {
+            return Ok(Some(Portrait {
+                photo,
+                region: Region { x, y, w, h },
+                chosen: true,
+            }));
+        }
+        Ok(self.face(db, auth)?.map(|(photo, region)| Portrait {
+            photo,
+            region,
+            chosen: false,
+        }))
+    }
+
+    /// Choose an area of a photo as the portrait of a person.
+    pub fn set_portrait(
+        db: &PgConnection,
+        person: i32,
+        photo: i32,
+        region: Region,
+    ) -> Result<(), Error> {
+        let values = (
+            po::photo_id.eq(photo),
+            po::region_x.eq(region.x),
+            po::region_y.eq(region.y),
+            po::region_w.eq(region.w),
+            po::region_h.eq(region.h),
+        );
+        diesel::insert_into(po::portraits)
+            .values((po::person_id.eq(person), values))
+            .on_conflict(po::person_id)
+            .do_update()
+            .set(values)
+            .execute(db)?;
+        Ok(())
+    }
+
+    /// Forget the chosen portrait of a person.
+    pub fn clear_portrait(
+        db: &PgConnection,
+        person: i32,
+    ) -> Result<(), Error> {
+        diesel::delete(po::portraits.filter(po::person_id.eq(person)))
+            .execute(db)?;
+        Ok(())
+    }
+
+    /// The ids of the people that has a portrait visible to the user.
+    pub fn with_portraits(
+        db: &PgConnection,
+        auth: bool,
+    ) -> Result<HashSet<i32>, Error> {
+        let mut result = HashSet::new();
+        result.extend(
+            Photo::query(auth)
+                .inner_join(po::portraits)
+                .select(po::person_id)
+                .load::<i32>(db)?,
+        );
+        result.extend(
+            Photo::query(auth)
+                .inner_join(ph::photo_people)
+                .filter(ph::region_x.is_not_null())
+                .select(ph::person_id)
+                .distinct()
+                .load::<i32>(db)?,
+        );
+        Ok(result)
+    }
+}
+
+/// A photo, and an area of it, representing a person.
+pub struct Portrait {
+    pub photo: Photo,
+    pub region: Region,
+    /// True if chosen by hand rather than found among the faces.
+    pub chosen: bool,
 }
 
 #[derive(Debug, Clone, Queryable)]
@@ -295,6 +787,136 @@ pub struct PhotoPerson {
     pub id: i32,
     pub photo_id: i32,
     pub person_id: i32,
+    pub region_x: Option<f32>,
+    pub region_y: Option<f32>,
+    pub region_w: Option<f32>,
+    pub region_h: Option<f32>,
+}
+
+type RegionColumns = (Option<f32>, Option<f32>, Option<f32>, Option<f32>);
+const REGION_COLUMNS: (
+    ph::region_x,
+    ph::region_y,
+    ph::region_w,
+    ph::region_h,
+) = (ph::region_x, ph::region_y, ph::region_w, ph::region_h);
+
+/// A rectangular area of a photo.
+///
+/// The position of the top left corner and the size are fractions of
+/// the width and height of the photo as shown, after rotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Region {
+    /// Create a region, clamped to the photo.
+    ///
+    /// Return None if nothing of the region is inside the photo.
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Option<Region> {
+        let (x0, y0) = (x.max(0.), y.max(0.));
+        let (x1, y1) = ((x + w).min(1.), (y + h).min(1.));
+        if x1 > x0 && y1 > y0 {
+            Some(Region {
+                x: x0,
+                y: y0,
+                w: x1 - x0,
+                h: y1 - y0,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn from_columns(columns: RegionColumns) -> Option<Region> {
+        match columns {
+            (Some(x), Some(y), Some(w), Some(h)) => {
+                Some(Region { x, y, w, h })
+            }
+            _ => None,
+        }
+    }
+
+    /// A region from an MWG-RS area, given as center and size as
+    /// fractions of the image as stored, before rotation.
+    pub fn from_mwg(
+        cx: f64,
+        cy: f64,
+        w: f64,
+        h: f64,
+        rotation: i16,
+    ) -> Option<Region> {
+        let (cx, cy, w, h) = rotate_area((cx, cy, w, h), rotation);
+        let (cx, cy, w, h) = (cx as f32, cy as f32, w as f32, h as f32);
+        Region::new(cx - w / 2., cy - h / 2., w, h)
+    }
+
+    /// This region as an MWG-RS area, the inverse of `from_mwg`.
+    pub fn to_mwg(self, rotation: i16) -> (f64, f64, f64, f64) {
+        let (w, h) = (f64::from(self.w), f64::from(self.h));
+        let (cx, cy) =
+            (f64::from(self.x) + w / 2., f64::from(self.y) + h / 2.);
+        rotate_area((cx, cy, w, h), (360 - rotation) % 360)
+    }
+
+    /// Css to place an element over this region of a photo.
+    pub fn css(&self) -> String {
+        format!(
+            "left:{:.2}%;top:{:.2}%;width:{:.2}%;height:{:.2}%",
+            self.x * 100.,
+            self.y * 100.,
+            self.w * 100.,
+            self.h * 100.,
+        )
+    }
+}
+
+/// Rotate an area given by center and size clockwise, by a multiple
+/// of 90 degrees.
+fn rotate_area(
+    (cx, cy, w, h): (f64, f64, f64, f64),
+    rotation: i16,
+) -> (f64, f64, f64, f64) {
+    match rotation {
+        90 => (1. - cy, cx, h, w),
+        180 => (1. - cx, 1. - cy, w, h),
+        270 => (cy, 1. - cx, h, w),
+        _ => (cx, cy, w, h),
+    }
+}
+
+#[test]
+fn region_mwg_roundtrip() {
+    let r = Region::new(0.1, 0.2, 0.3, 0.4).unwrap();
+    for rotation in &[0, 90, 180, 270] {
+        let (cx, cy, w, h) = r.to_mwg(*rotation);
+        let back = Region::from_mwg(cx, cy, w, h, *rotation).unwrap();
+        assert!((back.x - r.x).abs() < 1e-6, "{} {:?}", rotation, back);
+        assert!((back.y - r.y).abs() < 1e-6, "{} {:?}", rotation, back);
+        assert!((back.w - r.w).abs() < 1e-6, "{} {:?}", rotation, back);
+    }
+    // The top left corner of a photo rotated 90 degrees is the
+    // bottom left corner of the stored image.
+    let r = Region::from_mwg(0.05, 0.9, 0.1, 0.2, 90).unwrap();
+    assert!(r.x.abs() < 1e-6 && r.y.abs() < 1e-6, "{:?}", r);
+}
+
+#[test]
+fn region_clamped() {
+    assert_eq!(
+        Region::new(-0.25, 0.5, 0.5, 0.75),
+        Some(Region {
+            x: 0.,
+            y: 0.5,
+            w: 0.25,
+            h: 0.5
+        }),
+    );
+    assert_eq!(Region::new(1.1, 0.5, 0.3, 0.2), None);
 }
 
 #[derive(Debug, Clone, Queryable)]
@@ -304,12 +926,91 @@ pub struct Place {
     pub place_name: String,
     pub osm_id: Option<i64>,
     pub osm_level: Option<i16>,
+    pub parent_id: Option<i32>,
+    pub osm_tags: Option<String>,
+}
+
+impl Place {
+    /// The places containing this place, largest first.
+    pub fn load_ancestors(
+        &self,
+        db: &PgConnection,
+    ) -> Result<Vec<Place>, Error> {
+        let mut result = vec![];
+        let mut parent = self.parent_id;
+        while let Some(id) = parent {
+            // A cycle should not be possible, but don't loop forever.
+            if id == self.id || result.iter().any(|p: &Place| p.id == id) {
+                error!("Cycle in place hierarchy at {}", id);
+                break;
+            }
+            let place = l::places.find(id).first::<Place>(db)?;
+            parent = place.parent_id;
+            result.push(place);
+        }
+        result.reverse();
+        Ok(result)
+    }
+
+    /// Set parents of the places of a photo.
+    ///
+    /// All places of a photo contains the photo, so a place is
+    /// assumed to be contained in the place with the closest lower
+    /// osm level.  An existing parent is only replaced by a closer
+    /// one.
+    pub fn link_parents(db: &PgConnection, photo: i32) -> Result<(), Error> {
+        let places = l::places
+            .filter(l::osm_level.is_not_null())
+            .filter(
+                l::id.eq_any(
+                    pl::photo_places
+                        .select(pl::place_id)
+                        .filter(pl::photo_id.eq(photo)),
+                ),
+            )
+            .order(l::osm_level)
+            .load::<Place>(db)?;
+        for (i, place) in places.iter().enumerate() {
+            let parent = match places[..i]
+                .iter()
+                .rev()
+                .find(|p| p.osm_level < place.osm_level)
+            {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let closer = match place.parent_id {
+                None => true,
+                Some(old) => places
+                    .iter()
+                    .find(|p| p.id == old)
+                    .map(|old| old.osm_level < parent.osm_level)
+                    .unwrap_or(false),
+            };
+            if closer && place.parent_id != Some(parent.id) {
+                debug!("{} is in {}", place.place_name, parent.place_name);
+                diesel::update(l::places.find(place.id))
+                    .set(l::parent_id.eq(parent.id))
+                    .execute(db)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Facet for Place {
     fn by_slug(slug: &str, db: &PgConnection) -> Result<Place, Error> {
         l::places.filter(l::slug.eq(slug)).first(db)
     }
+    fn id(&self) -> i32 {
+        self.id
+    }
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+    fn name(&self) -> &str {
+        &self.place_name
+    }
 }
 
 #[derive(Debug, Clone, Queryable)]
@@ -324,6 +1025,7 @@ pub struct Camera {
     pub id: i32,
     pub manufacturer: String,
     pub model: String,
+    pub slug: String,
 }
 
 impl Camera {
@@ -340,13 +1042,383 @@ impl Camera {
         {
             Ok(camera)
         } else {
+            let taken = |slug: &str| {
+                diesel::select(exists(c::cameras.filter(c::slug.eq(slug))))
+                    .get_result(db)
+            };
+            let slug = slugify(modl);
+            let slug = if taken(&slug)? {
+                // The slug is probably taken by a model with the
+                // same name from another manufacturer.
+                unused_slug(&slugify(format!("{} {}", make, modl)), taken)?
+            } else {
+                slug
+            };
             diesel::insert_into(c::cameras)
-                .values((c::manufacturer.eq(make), c::model.eq(modl)))
+                .values((
+                    c::manufacturer.eq(make),
+                    c::model.eq(modl),
+                    c::slug.eq(&slug),
+                ))
+                .get_result(db)
+        }
+    }
+}
+
+impl Facet for Camera {
+    fn by_slug(slug: &str, db: &PgConnection) -> Result<Camera, Error> {
+        c::cameras.filter(c::slug.eq(slug)).first(db)
+    }
+    fn id(&self) -> i32 {
+        self.id
+    }
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+    fn name(&self) -> &str {
+        &self.model
+    }
+}
+
+#[derive(Debug, Clone, Identifiable, Queryable)]
+#[table_name = "lenses"]
+pub struct Lens {
+    pub id: i32,
+    pub model: String,
+    pub slug: String,
+}
+
+impl Lens {
+    pub fn get_or_create(
+        db: &PgConnection,
+        model: &str,
+    ) -> Result<Lens, Error> {
+        if let Some(lens) = le::lenses
+            .filter(le::model.eq(model))
+            .first::<Lens>(db)
+            .optional()?
+        {
+            Ok(lens)
+        } else {
+            // Different punctuation may give the same slug.
+            let slug = unused_slug(&slugify(model), |slug| {
+                diesel::select(exists(le::lenses.filter(le::slug.eq(slug))))
+                    .get_result(db)
+            })?;
+            diesel::insert_into(le::lenses)
+                .values((le::model.eq(model), le::slug.eq(&slug)))
                 .get_result(db)
         }
     }
 }
 
+/// The first of `base`, `base-2`, `base-3`, and so on, that is not taken.
+fn unused_slug(
+    base: &str,
+    taken: impl Fn(&str) -> Result<bool, Error>,
+) -> Result<String, Error> {
+    let mut slug = base.to_string();
+    let mut n = 1;
+    while taken(&slug)? {
+        n += 1;
+        slug = format!("{}-{}", base, n);
+    }
+    Ok(slug)
+}
+
+#[test]
+fn unused_slug_numbered() {
+    let taken = ["eos", "eos-2"];
+    let unused = |base| unused_slug(base, |s| Ok(taken.contains(&s)));
+    assert_eq!(unused("eos"), Ok("eos-3".into()));
+    assert_eq!(unused("ixus"), Ok("ixus".into()));
+}
+
+impl Facet for Lens {
+    fn by_slug(slug: &str, db: &PgConnection) -> Result<Lens, Error> {
+        le::lenses.filter(le::slug.eq(slug)).first(db)
+    }
+    fn id(&self) -> i32 {
+        self.id
+    }
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+    fn name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Capture details for a photo, as read from exif.
+///
+/// When saved, fields that are `None` are left unchanged in the database.
+#[derive(AsChangeset, Debug)]
+#[table_name = "photos"]
+pub struct CaptureDetails {
+    pub lens_id: Option<i32>,
+    pub exposure_time: Option<f32>,
+    pub aperture: Option<f32>,
+    pub iso: Option<i32>,
+    pub focal_length: Option<f32>,
+    pub focal_length_35: Option<i16>,
+    pub flash: Option<bool>,
+    pub altitude: Option<f32>,
+    pub direction: Option<f32>,
+    pub utc_date: Option<DateTime<Utc>>,
+}
+
+impl CaptureDetails {
+    /// True if saving these details would change the photo.
+    pub fn differs_from(&self, photo: &Photo) -> bool {
+        fn differs<T: PartialEq>(new: &Option<T>, old: &Option<T>) -> bool {
+            new.is_some() && new != old
+        }
+        differs(&self.lens_id, &photo.lens_id)
+            || differs(&self.exposure_time, &photo.exposure_time)
+            || differs(&self.aperture, &photo.aperture)
+            || differs(&self.iso, &photo.iso)
+            || differs(&self.focal_length, &photo.focal_length)
+            || differs(&self.focal_length_35, &photo.focal_length_35)
+            || differs(&self.flash, &photo.flash)
+            || differs(&self.altitude, &photo.altitude)
+            || differs(&self.direction, &photo.direction)
+            || differs(&self.utc_date, &photo.utc_date)
+    }
+
+    pub fn save_for(
+        &self,
+        db: &PgConnection,
+        photo: i32,
+    ) -> Result<Photo, Error> {
+        diesel::update(p::photos.find(photo))
+            .set(self)
+            .get_result(db)
+    }
+}
+
+/// Format an exposure time in seconds the way photographers do.
+///
+/// Short times are given as fractions, like "1/250 s".
+fn format_exposure_time(t: f32) -> String {
+    if t > 0.0 && t < 0.5 {
+        format!("1/{:.0} s", 1.0 / t)
+    } else {
+        format!("{} s", t)
+    }
+}
+
+#[test]
+fn exposure_time_short() {
+    assert_eq!(format_exposure_time(0.004), "1/250 s");
+}
+
+#[test]
+fn exposure_time_long() {
+    assert_eq!(format_exposure_time(2.5), "2.5 s");
+}
+
+/// A correction of the clock of a camera.
+///
+/// See the `adm::clock_shift` module.
+#[derive(Debug, Clone, Queryable)]
+pub struct ClockShift {
+    pub id: i32,
+    pub camera_id: i32,
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    pub seconds: i32,
+    pub created: NaiveDateTime,
+}
+
+/// A named search, shown as a "smart album".
+///
+/// The query is stored as the query string of the search page.
+#[derive(Debug, Clone, Queryable)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub query: String,
+    pub is_public: bool,
+    pub created: NaiveDateTime,
+}
+
+impl SavedSearch {
+    /// All saved searches visible to the user, by name.
+    pub fn list(db: &PgConnection, auth: bool) -> Result<Vec<Self>, Error> {
+        use crate::schema::saved_searches::dsl as s;
+        let query = s::saved_searches.order(s::name).into_boxed();
+        if auth {
+            query.load(db)
+        } else {
+            query.filter(s::is_public).load(db)
+        }
+    }
+
+    /// Get a saved search, if it is visible to the user.
+    pub fn by_slug(
+        slug: &str,
+        db: &PgConnection,
+        auth: bool,
+    ) -> Result<Self, Error> {
+        use crate::schema::saved_searches::dsl as s;
+        let query = s::saved_searches.filter(s::slug.eq(slug)).into_boxed();
+        if auth {
+            query.first(db)
+        } else {
+            query.filter(s::is_public).first(db)
+        }
+    }
+
+    /// Save a search, or replace the query of a search with the
+    /// same name.
+    ///
+    /// A name that gives an empty slug, or the same slug as another
+    /// saved search with a different name, is rejected.
+    pub fn save(
+        db: &PgConnection,
+        name: &str,
+        query: &str,
+        is_public: bool,
+    ) -> Result<Self, AdmError> {
+        use crate::schema::saved_searches::dsl as s;
+        let slug = slugify(name);
+        if slug.is_empty() {
+            return Err(AdmError::Other(format!(
+                "The name {:?} has no letters or digits",
+                name,
+            )));
+        }
+        if let Some(other) = s::saved_searches
+            .filter(s::slug.eq(&slug))
+            .filter(s::name.ne(name))
+            .select(s::name)
+            .first::<String>(db)
+            .optional()?
+        {
+            return Err(AdmError::Other(format!(
+                "The name {:?} is too similar to the saved search {:?}",
+                name, other,
+            )));
+        }
+        Ok(diesel::insert_into(s::saved_searches)
+            .values((
+                s::slug.eq(&slug),
+                s::name.eq(name),
+                s::query.eq(query),
+                s::is_public.eq(is_public),
+            ))
+            .on_conflict(s::slug)
+            .do_update()
+            .set((
+                s::name.eq(name),
+                s::query.eq(query),
+                s::is_public.eq(is_public),
+            ))
+            .get_result(db)?)
+    }
+
+    /// The url of the search page for this search.
+    pub fn url(&self) -> String {
+        format!("/search/{}", self.slug)
+    }
+}
+
+/// A comment on a photo, with the name of its author.
+#[derive(Debug, Clone, Queryable)]
+pub struct Comment {
+    pub id: i32,
+    pub photo_id: i32,
+    pub author: String,
+    pub body: String,
+    pub created: NaiveDateTime,
+}
+
+impl Comment {
+    /// The comments on a photo, oldest first.
+    pub fn for_photo(
+        db: &PgConnection,
+        photo: i32,
+    ) -> Result<Vec<Self>, Error> {
+        cm::comments
+            .inner_join(u::users)
+            .select((cm::id, cm::photo_id, u::username, cm::body, cm::created))
+            .filter(cm::photo_id.eq(photo))
+            .order(cm::created)
+            .load(db)
+    }
+
+    /// The latest comments on photos visible to the user.
+    pub fn recent(
+        db: &PgConnection,
+        auth: bool,
+        limit: i64,
+    ) -> Result<Vec<Self>, Error> {
+        let query = cm::comments
+            .inner_join(u::users)
+            .select((cm::id, cm::photo_id, u::username, cm::body, cm::created))
+            .order(cm::created.desc())
+            .limit(limit)
+            .into_boxed();
+        if auth {
+            query.load(db)
+        } else {
+            let public = p::photos.select(p::id).filter(p::is_public);
+            query.filter(cm::photo_id.eq_any(public)).load(db)
+        }
+    }
+
+    /// Add a comment by a user to a photo.
+    pub fn add(
+        db: &PgConnection,
+        photo: i32,
+        user: &str,
+        body: &str,
+    ) -> Result<i32, Error> {
+        let user = user_id(db, user)?;
+        diesel::insert_into(cm::comments)
+            .values((
+                cm::photo_id.eq(photo),
+                cm::user_id.eq(user),
+                cm::body.eq(body),
+            ))
+            .returning(cm::id)
+            .get_result(db)
+    }
+
+    /// Delete a comment, returning the id of the photo it was on.
+    pub fn delete(db: &PgConnection, id: i32) -> Result<i32, Error> {
+        diesel::delete(cm::comments.find(id))
+            .returning(cm::photo_id)
+            .get_result(db)
+    }
+}
+
+fn user_id(db: &PgConnection, name: &str) -> Result<i32, Error> {
+    u::users
+        .filter(u::username.eq(name))
+        .select(u::id)
+        .first(db)
+}
+
+/// True if a user wants groups of photos picked by their own ratings.
+pub fn uses_own_ratings(db: &PgConnection, user: &str) -> Result<bool, Error> {
+    u::users
+        .filter(u::username.eq(user))
+        .select(u::own_ratings)
+        .first(db)
+}
+
+pub fn set_own_ratings(
+    db: &PgConnection,
+    user: &str,
+    on: bool,
+) -> Result<usize, Error> {
+    diesel::update(u::users.filter(u::username.eq(user)))
+        .set(u::own_ratings.eq(on))
+        .execute(db)
+}
+
 #[derive(Debug, Clone)]
 pub struct Coord {
     pub x: f64,
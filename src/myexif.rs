@@ -1,16 +1,21 @@
 //! Extract all the exif data I care about
 use crate::adm::result::Error;
-use chrono::{Date, Local, NaiveDate, NaiveDateTime, Utc};
+use crate::xmp::{read_faces, Face};
+use chrono::{
+    Date, DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use exif::{Field, In, Reader, Tag, Value};
 use log::{debug, error, warn};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, Read};
 use std::path::Path;
 use std::str::from_utf8;
 
 #[derive(Debug, Default)]
 pub struct ExifData {
     dateval: Option<NaiveDateTime>,
+    offset: Option<FixedOffset>,
     gpsdate: Option<Date<Utc>>,
     gpstime: Option<(u8, u8, u8)>,
     make: Option<String>,
@@ -22,8 +27,30 @@ pub struct ExifData {
     longval: Option<f64>,
     latref: Option<String>,
     longref: Option<String>,
+    altval: Option<f64>,
+    altref: Option<u32>,
+    direction: Option<f64>,
+    flash: Option<u32>,
+    pub lens: Option<String>,
+    pub exposure_time: Option<f64>,
+    pub aperture: Option<f64>,
+    pub iso: Option<u32>,
+    pub focal_length: Option<f64>,
+    pub focal_length_35: Option<u32>,
+    description: Option<String>,
+    iptc_title: Option<String>,
+    iptc_caption: Option<String>,
+    pub faces: Vec<Face>,
 }
 
+/// Descriptions written by cameras rather than by people.
+const PLACEHOLDER_DESCRIPTIONS: &[&str] = &[
+    "OLYMPUS DIGITAL CAMERA",
+    "SONY DSC",
+    "DCIM",
+    "Exif_JPEG_PICTURE",
+];
+
 impl ExifData {
     pub fn read_from(path: &Path) -> Result<Self, Error> {
         let mut result = Self::default();
@@ -42,6 +69,12 @@ impl ExifData {
                     if result.dateval.is_none() {
                         result.dateval = Some(d)
                     }
+                } else if let Some(o) = is_offset(f, Tag::OffsetTimeOriginal) {
+                    result.offset = Some(o);
+                } else if let Some(o) = is_offset(f, Tag::OffsetTime) {
+                    if result.offset.is_none() {
+                        result.offset = Some(o);
+                    }
                 } else if let Some(s) = is_string(f, Tag::Make) {
                     result.make = Some(s.to_string());
                 } else if let Some(s) = is_string(f, Tag::Model) {
@@ -64,41 +97,126 @@ impl ExifData {
                     result.latref = Some(s.to_string());
                 } else if let Some(s) = is_string(f, Tag::GPSLongitudeRef) {
                     result.longref = Some(s.to_string());
-                /*
-                } else if let Some(s) = is_string(f, Tag::GPSImgDirectionRef) {
-                    println!("  direction ref: {}", s);
-                } else if let Some(s) = is_string(f, Tag::GPSImgDirection) {
-                    println!("  direction: {}", s);
-                */
+                } else if let Some(alt) = is_rational(f, Tag::GPSAltitude) {
+                    result.altval = Some(alt);
+                } else if let Some(r) = is_u32(f, Tag::GPSAltitudeRef) {
+                    result.altref = Some(r);
+                } else if let Some(d) = is_rational(f, Tag::GPSImgDirection) {
+                    result.direction = Some(d);
+                } else if let Some(s) = is_string(f, Tag::LensModel) {
+                    result.lens = Some(s.trim().to_string());
+                } else if let Some(t) = is_rational(f, Tag::ExposureTime) {
+                    result.exposure_time = Some(t);
+                } else if let Some(a) = is_rational(f, Tag::FNumber) {
+                    result.aperture = Some(a);
+                } else if let Some(iso) =
+                    is_u32(f, Tag::PhotographicSensitivity)
+                {
+                    result.iso = Some(iso);
+                } else if let Some(l) = is_rational(f, Tag::FocalLength) {
+                    result.focal_length = Some(l);
+                } else if let Some(l) = is_u32(f, Tag::FocalLengthIn35mmFilm) {
+                    result.focal_length_35 = Some(l);
+                } else if let Some(flash) = is_u32(f, Tag::Flash) {
+                    result.flash = Some(flash);
                 } else if let Some(d) = is_date(f, Tag::GPSDateStamp) {
                     result.gpsdate = Some(d);
                 } else if let Some(hms) = is_time(f, Tag::GPSTimeStamp) {
                     result.gpstime = Some(hms);
+                } else if let Some(s) = is_string(f, Tag::ImageDescription) {
+                    result.description = Some(s.to_string());
                 }
             }
             //println!("    {} ({}) {:?}", f.tag, f.thumbnail, f.value);
         }
+        for (dataset, value) in read_iptc(path).unwrap_or_default() {
+            match dataset {
+                5 => result.iptc_title = Some(value),
+                120 => result.iptc_caption = Some(value),
+                _ => (),
+            }
+        }
+        result.faces = read_faces(path);
         Ok(result)
     }
 
+    /// The title of the photo, from the IPTC object name.
+    pub fn title(&self) -> Option<&str> {
+        nonblank(&self.iptc_title)
+    }
+    /// A description of the photo, from exif or the IPTC caption.
+    ///
+    /// Placeholder descriptions written by some cameras are ignored.
+    pub fn description(&self) -> Option<&str> {
+        nonblank(&self.description)
+            .filter(|d| !PLACEHOLDER_DESCRIPTIONS.contains(d))
+            .or_else(|| nonblank(&self.iptc_caption))
+    }
+
+    /// The local (wall-clock) time when the photo was taken.
+    ///
+    /// GPS time is preferred when the time zone is known, since
+    /// camera clocks are often a bit off.
     pub fn date(&self) -> Option<NaiveDateTime> {
-        // Note: I probably return and store datetime with tz,
-        // possibly utc, instead.
-        if let (&Some(date), &Some((h, m, s))) = (&self.gpsdate, &self.gpstime)
-        {
-            let naive = date
-                .and_hms(u32::from(h), u32::from(m), u32::from(s))
-                .with_timezone(&Local)
-                .naive_local();
-            debug!("GPS Date {}, {}:{}:{} => {}", date, h, m, s, naive);
-            Some(naive)
-        } else if let Some(date) = self.dateval {
+        if let Some(utc) = self.gps_utc() {
+            if let Some(offset) = self.offset {
+                let local = utc.with_timezone(&offset).naive_local();
+                debug!("GPS Date {} with offset {} => {}", utc, offset, local);
+                return Some(local);
+            }
+            if let Some(tz) = self.timezone() {
+                let local = utc.with_timezone(&tz).naive_local();
+                debug!("GPS Date {} in {} => {}", utc, tz.name(), local);
+                return Some(local);
+            }
+        }
+        if let Some(date) = self.dateval {
             Some(date)
+        } else if let Some(utc) = self.gps_utc() {
+            warn!("Unknown time zone, using GPS date {} as is", utc);
+            Some(utc.naive_utc())
         } else {
             warn!("No date found in exif");
             None
         }
     }
+    /// The instant when the photo was taken, if the time zone is known.
+    ///
+    /// The time zone is taken from the exif offset if present,
+    /// otherwise inferred from the position.
+    pub fn utc_date(&self) -> Option<DateTime<Utc>> {
+        if let Some(utc) = self.gps_utc() {
+            Some(utc)
+        } else if let (Some(date), Some(offset)) = (self.dateval, self.offset)
+        {
+            to_utc(&offset, &date)
+        } else if let (Some(date), Some(tz)) = (self.dateval, self.timezone())
+        {
+            to_utc(&tz, &date)
+        } else {
+            None
+        }
+    }
+    fn gps_utc(&self) -> Option<DateTime<Utc>> {
+        if let (&Some(date), &Some((h, m, s))) = (&self.gpsdate, &self.gpstime)
+        {
+            date.and_hms_opt(u32::from(h), u32::from(m), u32::from(s))
+        } else {
+            None
+        }
+    }
+    /// The time zone at the position of the photo, if any.
+    fn timezone(&self) -> Option<Tz> {
+        let (lat, long) = self.position()?;
+        if lat.abs() > 90. || long.abs() > 180. {
+            warn!("Position {}, {} out of range", lat, long);
+            return None;
+        }
+        tz_search::lookup(lat, long)?
+            .parse()
+            .map_err(|e| warn!("Unknown time zone: {}", e))
+            .ok()
+    }
     pub fn camera(&self) -> Option<(&str, &str)> {
         if let (&Some(ref make), &Some(ref model)) = (&self.make, &self.model)
         {
@@ -114,6 +232,23 @@ impl ExifData {
             None
         }
     }
+    /// Altitude in meters above sea level, negative if below.
+    pub fn altitude(&self) -> Option<f64> {
+        match (self.altref, self.altval) {
+            (Some(1), Some(alt)) => Some(-alt),
+            (_, alt) => alt,
+        }
+    }
+    /// Direction of the camera, in degrees.
+    pub fn direction(&self) -> Option<f64> {
+        self.direction
+    }
+    /// True if the flash fired.
+    pub fn flash(&self) -> Option<bool> {
+        // Bit 0 of the flash value tells if the flash fired, the
+        // other bits describe mode, return light, etc.
+        self.flash.map(|f| f & 1 == 1)
+    }
     fn lat(&self) -> Option<f64> {
         match (&self.latref, self.latval) {
             (&Some(ref r), Some(lat)) if r == "N" => Some(lat.abs()),
@@ -154,6 +289,122 @@ impl ExifData {
     }
 }
 
+fn nonblank(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Read the IPTC application record of a jpeg file.
+///
+/// The IPTC data is stored in a Photoshop resource block in the
+/// APP13 segment.
+fn read_iptc(path: &Path) -> Result<Vec<(u8, String)>, io::Error> {
+    Ok(jpeg_segment(path, 0xED, b"Photoshop 3.0\0")?
+        .map(|resources| iptc_datasets(&resources))
+        .unwrap_or_default())
+}
+
+/// Get the data of the first segment of a jpeg file that has the
+/// given marker and starts with the given prefix.
+///
+/// Only the metadata segments before the image data are searched.
+/// The prefix is not included in the result.
+pub fn jpeg_segment(
+    path: &Path,
+    marker: u8,
+    prefix: &[u8],
+) -> Result<Option<Vec<u8>>, io::Error> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buf = [0u8; 4];
+    file.read_exact(&mut buf[..2])?;
+    if buf[..2] != [0xFF, 0xD8] {
+        return Ok(None);
+    }
+    loop {
+        file.read_exact(&mut buf)?;
+        let len = u16::from_be_bytes([buf[2], buf[3]]);
+        // 0xDA is start of scan, after that there is only image data.
+        if buf[0] != 0xFF || buf[1] == 0xDA || len < 2 {
+            return Ok(None);
+        }
+        let mut data = vec![0; usize::from(len) - 2];
+        file.read_exact(&mut data)?;
+        if buf[1] == marker && data.starts_with(prefix) {
+            data.drain(..prefix.len());
+            return Ok(Some(data));
+        }
+    }
+}
+
+/// Get the application record datasets (like 5 for object name and
+/// 120 for caption) from Photoshop image resources.
+fn iptc_datasets(resources: &[u8]) -> Vec<(u8, String)> {
+    let mut result = vec![];
+    let mut res = resources;
+    while res.len() >= 12 && res.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([res[4], res[5]]);
+        // The name is a pascal string, padded to even length.
+        let name_len = usize::from(res[6]) + 1;
+        let start = 6 + name_len + name_len % 2;
+        let size = match res.get(start..start + 4) {
+            Some(s) => u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as usize,
+            None => break,
+        };
+        let data = match res.get(start + 4..start + 4 + size) {
+            Some(data) => data,
+            None => break,
+        };
+        if id == 0x0404 {
+            let mut iim = data;
+            while iim.len() >= 5 && iim[0] == 0x1C {
+                let size = usize::from(u16::from_be_bytes([iim[3], iim[4]]));
+                let value = match iim.get(5..5 + size) {
+                    // Extended sizes are not used for text.
+                    Some(value) if size & 0x8000 == 0 => value,
+                    _ => break,
+                };
+                if iim[1] == 2 {
+                    // Should be utf-8, but older files may be latin-1.
+                    let value =
+                        from_utf8(value).map(str::to_string).unwrap_or_else(
+                            |_| value.iter().map(|b| char::from(*b)).collect(),
+                        );
+                    result.push((iim[2], value));
+                }
+                iim = &iim[5 + size..];
+            }
+        }
+        res = res.get(start + 4 + size + size % 2..).unwrap_or_default();
+    }
+    result
+}
+
+#[test]
+fn iptc_caption_and_title() {
+    let mut iim = vec![0x1C, 1, 0, 0, 2, 0, 4];
+    iim.extend(&[0x1C, 2, 5, 0, 6]);
+    iim.extend(b"Sunset");
+    iim.extend(&[
+        0x1C, 2, 120, 0, 7, b'K', 0xE4, b'l', b'l', b'a', b'r', b'e',
+    ]);
+    let mut resources = b"8BIM\x04\x04\0\0".to_vec();
+    resources.extend(&(iim.len() as u32).to_be_bytes());
+    resources.extend(&iim);
+    assert_eq!(
+        iptc_datasets(&resources),
+        vec![(5, "Sunset".to_string()), (120, "Källare".to_string())],
+    );
+}
+
+#[test]
+fn placeholder_description() {
+    let exif = ExifData {
+        description: Some("OLYMPUS DIGITAL CAMERA  ".into()),
+        iptc_caption: Some("Picnic".into()),
+        ..ExifData::default()
+    };
+    assert_eq!(exif.description(), Some("Picnic"));
+}
+
 fn is_lat_long(f: &Field, tag: Tag) -> Option<f64> {
     if f.tag == tag {
         match f.value {
@@ -171,6 +422,22 @@ fn is_lat_long(f: &Field, tag: Tag) -> Option<f64> {
     }
 }
 
+fn is_rational(f: &Field, tag: Tag) -> Option<f64> {
+    if f.tag == tag {
+        match f.value {
+            Value::Rational(ref v) if v.len() == 1 && v[0].denom != 0 => {
+                Some(v[0].to_f64())
+            }
+            ref v => {
+                println!("ERROR: Bad value for {}: {:?}", tag, v);
+                None
+            }
+        }
+    } else {
+        None
+    }
+}
+
 fn is_datetime(f: &Field, tag: Tag) -> Option<NaiveDateTime> {
     if f.tag == tag {
         single_ascii(&f.value)
@@ -184,6 +451,58 @@ fn is_datetime(f: &Field, tag: Tag) -> Option<NaiveDateTime> {
     }
 }
 
+fn to_utc<T: TimeZone>(tz: &T, date: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    // When the clock is turned back, a local time is ambiguous.
+    // Any of the alternatives is better than none.
+    tz.from_local_datetime(date)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn is_offset(f: &Field, tag: Tag) -> Option<FixedOffset> {
+    if f.tag == tag {
+        single_ascii(&f.value)
+            .ok()
+            .and_then(parse_offset)
+            .or_else(|| {
+                println!("ERROR: Expected offset for {}: {:?}", tag, f.value);
+                None
+            })
+    } else {
+        None
+    }
+}
+
+/// Parse an exif time offset, like "+02:00" or "-05:30".
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.get(0..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let h = s.get(1..3)?.parse::<i32>().ok()?;
+    let m = s.get(4..6)?.parse::<i32>().ok()?;
+    if s.get(3..4)? != ":" || s.len() != 6 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (h * 60 + m) * 60)
+}
+
+#[test]
+fn parse_offset_east() {
+    assert_eq!(parse_offset("+02:00"), FixedOffset::east_opt(7200));
+}
+
+#[test]
+fn parse_offset_west() {
+    assert_eq!(parse_offset("-05:30"), FixedOffset::west_opt(19800));
+}
+
+#[test]
+fn parse_offset_bad() {
+    assert_eq!(parse_offset("   :  "), None);
+}
+
 fn is_date(f: &Field, tag: Tag) -> Option<Date<Utc>> {
     if f.tag == tag {
         single_ascii(&f.value)
@@ -241,6 +560,7 @@ fn is_u32(f: &Field, tag: Tag) -> Option<u32> {
         match &f.value {
             &Value::Long(ref v) if v.len() == 1 => Some(v[0]),
             &Value::Short(ref v) if v.len() == 1 => Some(u32::from(v[0])),
+            &Value::Byte(ref v) if v.len() == 1 => Some(u32::from(v[0])),
             v => {
                 println!("ERROR: Unsuppored value for {}: {:?}", tag, v);
                 None
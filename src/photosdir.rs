@@ -1,7 +1,7 @@
-use crate::models::Photo;
+use crate::models::{Photo, Region};
 use crate::myexif::ExifData;
 use image::imageops::FilterType;
-use image::{self, GenericImageView, ImageError, ImageFormat};
+use image::{self, DynamicImage, GenericImageView, ImageError, ImageFormat};
 use log::{debug, info, warn};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
@@ -35,16 +35,36 @@ impl PhotosDir {
         } else {
             img
         };
-        let img = match photo.rotation {
-            _x @ 0..=44 | _x @ 315..=360 => img,
-            _x @ 45..=134 => img.rotate90(),
-            _x @ 135..=224 => img.rotate180(),
-            _x @ 225..=314 => img.rotate270(),
-            x => {
-                warn!("Should rotate photo {} deg, which is unsupported", x);
-                img
-            }
-        };
+        let img = rotate(img, photo.rotation);
+        let mut buf = Vec::new();
+        img.write_to(&mut buf, ImageFormat::Jpeg)?;
+        Ok(buf)
+    }
+
+    /// A square thumbnail of a region of a photo.
+    ///
+    /// The region is extended to a square around its center, as far
+    /// as the photo allows.
+    pub fn scale_region(
+        &self,
+        photo: &Photo,
+        region: Region,
+        size: u32,
+    ) -> Result<Vec<u8>, ImageError> {
+        let path = self.basedir.join(&photo.path);
+        info!("Should open {:?} for a region", path);
+        let img = rotate(image::open(path)?, photo.rotation);
+        let (w, h) = (img.width() as f32, img.height() as f32);
+        let side = (region.w * w).max(region.h * h).min(w).min(h);
+        let left = ((region.x + region.w / 2.) * w - side / 2.)
+            .max(0.)
+            .min(w - side);
+        let top = ((region.y + region.h / 2.) * h - side / 2.)
+            .max(0.)
+            .min(h - side);
+        let img = img
+            .crop_imm(left as u32, top as u32, side as u32, side as u32)
+            .thumbnail(size, size);
         let mut buf = Vec::new();
         img.write_to(&mut buf, ImageFormat::Jpeg)?;
         Ok(buf)
@@ -101,3 +121,16 @@ impl PhotosDir {
         })
     }
 }
+
+fn rotate(img: DynamicImage, rotation: i16) -> DynamicImage {
+    match rotation {
+        _x @ 0..=44 | _x @ 315..=360 => img,
+        _x @ 45..=134 => img.rotate90(),
+        _x @ 135..=224 => img.rotate180(),
+        _x @ 225..=314 => img.rotate270(),
+        x => {
+            warn!("Should rotate photo {} deg, which is unsupported", x);
+            img
+        }
+    }
+}
@@ -10,6 +10,54 @@ table! {
         id -> Int4,
         manufacturer -> Varchar,
         model -> Varchar,
+        slug -> Varchar,
+    }
+}
+
+table! {
+    clock_shift_photos (id) {
+        id -> Int4,
+        shift_id -> Int4,
+        photo_id -> Int4,
+        old_date -> Nullable<Timestamp>,
+        old_utc_date -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    clock_shifts (id) {
+        id -> Int4,
+        camera_id -> Int4,
+        since -> Timestamp,
+        until -> Timestamp,
+        seconds -> Int4,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    comments (id) {
+        id -> Int4,
+        photo_id -> Int4,
+        user_id -> Int4,
+        body -> Text,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    favourites (photo_id, user_id) {
+        photo_id -> Int4,
+        user_id -> Int4,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    lenses (id) {
+        id -> Int4,
+        model -> Varchar,
+        slug -> Varchar,
     }
 }
 
@@ -26,6 +74,10 @@ table! {
         id -> Int4,
         photo_id -> Int4,
         person_id -> Int4,
+        region_x -> Nullable<Float4>,
+        region_y -> Nullable<Float4>,
+        region_w -> Nullable<Float4>,
+        region_h -> Nullable<Float4>,
     }
 }
 
@@ -49,6 +101,22 @@ table! {
         attribution_id -> Nullable<Int4>,
         width -> Int4,
         height -> Int4,
+        lens_id -> Nullable<Int4>,
+        exposure_time -> Nullable<Float4>,
+        aperture -> Nullable<Float4>,
+        iso -> Nullable<Int4>,
+        focal_length -> Nullable<Float4>,
+        focal_length_35 -> Nullable<Int2>,
+        flash -> Nullable<Bool>,
+        altitude -> Nullable<Float4>,
+        direction -> Nullable<Float4>,
+        utc_date -> Nullable<Timestamptz>,
+        date_manual -> Bool,
+        created -> Timestamp,
+        updated -> Timestamp,
+        title -> Nullable<Varchar>,
+        description -> Nullable<Text>,
+        caption_manual -> Bool,
     }
 }
 
@@ -60,6 +128,25 @@ table! {
     }
 }
 
+table! {
+    place_names (id) {
+        id -> Int4,
+        place_id -> Int4,
+        lang -> Varchar,
+        name -> Varchar,
+    }
+}
+
+table! {
+    place_queue (photo_id) {
+        photo_id -> Int4,
+        added -> Timestamp,
+        attempts -> Int2,
+        last_error -> Nullable<Varchar>,
+        no_places -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     places (id) {
         id -> Int4,
@@ -67,6 +154,19 @@ table! {
         place_name -> Varchar,
         osm_id -> Nullable<Int8>,
         osm_level -> Nullable<Int2>,
+        parent_id -> Nullable<Int4>,
+        osm_tags -> Nullable<Text>,
+    }
+}
+
+table! {
+    portraits (person_id) {
+        person_id -> Int4,
+        photo_id -> Int4,
+        region_x -> Float4,
+        region_y -> Float4,
+        region_w -> Float4,
+        region_h -> Float4,
     }
 }
 
@@ -76,6 +176,27 @@ table! {
         photo_id -> Int4,
         latitude -> Int4,
         longitude -> Int4,
+        source -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    ratings (photo_id, user_id) {
+        photo_id -> Int4,
+        user_id -> Int4,
+        grade -> Int2,
+        updated -> Timestamp,
+    }
+}
+
+table! {
+    saved_searches (id) {
+        id -> Int4,
+        slug -> Varchar,
+        name -> Varchar,
+        query -> Varchar,
+        is_public -> Bool,
+        created -> Timestamp,
     }
 }
 
@@ -92,9 +213,17 @@ table! {
         id -> Int4,
         username -> Varchar,
         password -> Varchar,
+        own_ratings -> Bool,
     }
 }
 
+joinable!(clock_shift_photos -> clock_shifts (shift_id));
+joinable!(clock_shift_photos -> photos (photo_id));
+joinable!(clock_shifts -> cameras (camera_id));
+joinable!(comments -> photos (photo_id));
+joinable!(comments -> users (user_id));
+joinable!(favourites -> photos (photo_id));
+joinable!(favourites -> users (user_id));
 joinable!(photo_people -> people (person_id));
 joinable!(photo_people -> photos (photo_id));
 joinable!(photo_places -> photos (photo_id));
@@ -103,18 +232,35 @@ joinable!(photo_tags -> photos (photo_id));
 joinable!(photo_tags -> tags (tag_id));
 joinable!(photos -> attributions (attribution_id));
 joinable!(photos -> cameras (camera_id));
+joinable!(photos -> lenses (lens_id));
+joinable!(place_names -> places (place_id));
+joinable!(place_queue -> photos (photo_id));
+joinable!(portraits -> people (person_id));
+joinable!(portraits -> photos (photo_id));
 joinable!(positions -> photos (photo_id));
+joinable!(ratings -> photos (photo_id));
+joinable!(ratings -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
     attributions,
     cameras,
+    clock_shift_photos,
+    clock_shifts,
+    comments,
+    favourites,
+    lenses,
     people,
     photo_people,
     photo_places,
     photos,
     photo_tags,
+    place_names,
+    place_queue,
     places,
+    portraits,
     positions,
+    ratings,
+    saved_searches,
     tags,
     users,
 );
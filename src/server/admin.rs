@@ -1,29 +1,337 @@
 //! Admin-only views, generally called by javascript.
-use super::{not_found, permission_denied, redirect_to_img, Context};
-use crate::models::{Coord, Photo, SizeTag};
+use super::render_ructe::RenderRucte;
+use super::{
+    error_response, not_found, permission_denied, redirect, redirect_to_img,
+    Context, PhotoLink,
+};
+use crate::adm::clock_shift::{self, ShiftForm};
+use crate::adm::geotag::{self, GeotagForm};
+use crate::adm::result::Error;
+use crate::fetch_places;
+use crate::models::{
+    set_own_ratings, Camera, Comment, Coord, Person, Photo, Region,
+    SavedSearch, SizeTag,
+};
+use crate::templates;
+use chrono::Duration;
 use diesel::{self, prelude::*};
 use log::{info, warn};
 use serde::Deserialize;
 use slug::slugify;
+use std::collections::HashMap;
 use warp::filters::BoxedFilter;
-use warp::http::Response;
+use warp::http::{Response, StatusCode};
 use warp::{Filter, Reply};
 
 pub fn routes(s: BoxedFilter<(Context,)>) -> BoxedFilter<(impl Reply,)> {
-    use warp::{body::form, path, post2 as post};
-    let route = path("grade")
+    use warp::filters::query::query;
+    use warp::path::end;
+    use warp::{body::form, get2 as get, path, post2 as post};
+    let route = path("clockshift")
+        .and(end())
         .and(s.clone())
         .and(form())
-        .map(set_grade)
+        .map(apply_clockshift)
+        .or(path("clockshift")
+            .and(path("undo"))
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(undo_clockshift))
+        .unify()
+        .or(path("caption").and(s.clone()).and(form()).map(set_caption))
+        .unify()
+        .or(path("comment")
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(add_comment))
+        .unify()
+        .or(path("comment")
+            .and(path("delete"))
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(delete_comment))
+        .unify()
+        .or(path("date").and(s.clone()).and(form()).map(set_date))
+        .unify()
+        .or(path("favourite")
+            .and(s.clone())
+            .and(form())
+            .map(toggle_favourite))
+        .unify()
+        .or(path("geotag")
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(apply_geotag))
+        .unify()
+        .or(path("grade").and(s.clone()).and(form()).map(set_grade))
+        .unify()
         .or(path("locate").and(s.clone()).and(form()).map(set_location))
         .unify()
         .or(path("person").and(s.clone()).and(form()).map(set_person))
         .unify()
+        .or(path("portrait")
+            .and(s.clone())
+            .and(form())
+            .map(set_portrait))
+        .unify()
+        .or(path("rating").and(s.clone()).and(form()).map(set_rating))
+        .unify()
+        .or(path("ratings").and(s.clone()).and(form()).map(set_ratings))
+        .unify()
+        .or(path("region").and(s.clone()).and(form()).map(set_region))
+        .unify()
         .or(path("rotate").and(s.clone()).and(form()).map(rotate))
         .unify()
+        .or(path("search")
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(save_search))
+        .unify()
+        .or(path("search")
+            .and(path("delete"))
+            .and(end())
+            .and(s.clone())
+            .and(form())
+            .map(delete_search))
+        .unify()
         .or(path("tag").and(s.clone()).and(form()).map(set_tag))
         .unify();
-    post().and(route).boxed()
+    post()
+        .and(route)
+        .or(get()
+            .and(path("clockshift"))
+            .and(end())
+            .and(s.clone())
+            .and(query())
+            .map(clockshift))
+        .unify()
+        .or(get()
+            .and(path("geotag"))
+            .and(end())
+            .and(s.clone())
+            .and(query())
+            .map(geotag))
+        .unify()
+        .boxed()
+}
+
+/// Show the clock shift form, a preview of the shift, and
+/// previous shifts.
+fn clockshift(context: Context, form: ShiftForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    clockshift_page(&context, &form, None)
+}
+
+fn clockshift_page(
+    context: &Context,
+    form: &ShiftForm,
+    error: Option<Error>,
+) -> Response<Vec<u8>> {
+    let db = context.db().unwrap();
+    let (preview, error) = if form.is_empty() || error.is_some() {
+        (vec![], error)
+    } else {
+        match form.validate(&db) {
+            Ok(shift) => {
+                let photos = shift.affected(&db).unwrap();
+                let links = photos
+                    .iter()
+                    .map(|p| {
+                        let mut link = PhotoLink::no_title(p);
+                        link.lable = p.date.and_then(|old| {
+                            let new = shift.new_date(p)?;
+                            Some(format!(
+                                "{} \u{2192} {}",
+                                old.format("%F %T"),
+                                new.format("%F %T"),
+                            ))
+                        });
+                        link
+                    })
+                    .collect::<Vec<_>>();
+                (links, None)
+            }
+            Err(e) => (vec![], Some(e)),
+        }
+    };
+    let cameras = {
+        use crate::schema::cameras::dsl as c;
+        c::cameras.order(c::model).load::<Camera>(&db).unwrap()
+    };
+    let shifts = clock_shift::list(&db)
+        .unwrap()
+        .into_iter()
+        .map(|(shift, camera)| {
+            let n = clock_shift::count_photos(&db, shift.id).unwrap();
+            (shift, camera, n)
+        })
+        .collect::<Vec<_>>();
+    Response::builder().html(|o| {
+        templates::clockshift(
+            o,
+            context,
+            form,
+            &error.map(|e| e.to_string()),
+            &cameras,
+            &preview,
+            &shifts,
+        )
+    })
+}
+
+fn apply_clockshift(context: Context, form: ShiftForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    match form.validate(&db).and_then(|shift| shift.apply(&db)) {
+        Ok((id, n)) => {
+            info!("Clock shift #{}: {} photos by {}", id, n, form.offset,);
+            redirect("/adm/clockshift")
+        }
+        Err(e) => {
+            warn!("Failed to shift clock: {}", e);
+            clockshift_page(&context, &form, Some(e))
+        }
+    }
+}
+
+fn undo_clockshift(context: Context, form: UndoForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    match clock_shift::undo(&context.db().unwrap(), form.shift) {
+        Ok(n) => {
+            info!("Undid clock shift #{} of {} photos", form.shift, n);
+            redirect("/adm/clockshift")
+        }
+        Err(e) => {
+            warn!("Failed to undo clock shift #{}: {}", form.shift, e);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UndoForm {
+    shift: i32,
+}
+
+/// Show proposed positions for photos that has none, based on
+/// photos taken close in time.
+fn geotag(context: Context, form: GeotagForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    let window = Duration::seconds(form.window.into());
+    let proposals = geotag::propose_nearby(&db, window, form.limit)
+        .unwrap()
+        .into_iter()
+        .map(|p| {
+            let mut link = PhotoLink::no_title(&p.photo);
+            link.lable = p.photo.date.map(|d| d.format("%F %T").to_string());
+            (link, p.lat, p.lng)
+        })
+        .collect::<Vec<_>>();
+    Response::builder()
+        .html(|o| templates::geotag(o, &context, &form, &proposals))
+}
+
+/// Apply accepted proposals from the geotag page.
+///
+/// Each accepted proposal is a `p<photo id>` field with the value
+/// `<lat>,<lng>` in microdegrees.  Other fields are ignored.
+fn apply_geotag(
+    context: Context,
+    form: HashMap<String, String>,
+) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    let accepted = form.iter().filter_map(|(key, value)| {
+        let photo = key.strip_prefix('p')?.parse::<i32>().ok()?;
+        let mut coords = value.split(',').map(|v| v.parse::<i32>().ok());
+        Some((photo, coords.next()??, coords.next()??))
+    });
+    let mut n = 0;
+    for (photo, lat, lng) in accepted {
+        let result = geotag::save_nearby_position(&db, photo, lat, lng)
+            .and_then(|saved| {
+                if saved {
+                    fetch_places::enqueue(&db, photo)?;
+                }
+                Ok(saved)
+            });
+        match result {
+            Ok(true) => n += 1,
+            Ok(false) => info!("Kept the current position of #{}", photo),
+            Err(e) => warn!("Failed to set position of #{}: {}", photo, e),
+        }
+    }
+    info!("Set position of {} photos from nearby photos", n);
+    let params = ["window", "limit"]
+        .iter()
+        .filter_map(|k| Some(format!("{}={}", k, form.get(*k)?)))
+        .collect::<Vec<_>>();
+    redirect(&format!("/adm/geotag?{}", params.join("&")))
+}
+
+/// Save a search, and show it.
+fn save_search(context: Context, form: SaveSearchForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    let name = form.name.trim();
+    match SavedSearch::save(&db, name, &form.query, form.public.is_some()) {
+        Ok(saved) => {
+            info!("Saved search {:?}: {}", saved.name, saved.query);
+            redirect(&saved.url())
+        }
+        Err(Error::Other(e)) => {
+            super::search::save_failed(&context, &form.query, &e)
+        }
+        Err(e) => {
+            warn!("Failed to save search {:?}: {}", name, e);
+            redirect(&format!("/search/?{}", form.query))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveSearchForm {
+    name: String,
+    query: String,
+    public: Option<String>,
+}
+
+fn delete_search(context: Context, form: SlugForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    use crate::schema::saved_searches::dsl as s;
+    let db = context.db().unwrap();
+    match diesel::delete(s::saved_searches.filter(s::slug.eq(&form.slug)))
+        .execute(&db)
+    {
+        Ok(n) => info!("Deleted {} saved search {:?}", n, form.slug),
+        Err(e) => warn!("Failed to delete search {:?}: {}", form.slug, e),
+    }
+    redirect("/")
+}
+
+#[derive(Deserialize)]
+struct SlugForm {
+    slug: String,
 }
 
 fn rotate(context: Context, form: RotateForm) -> Response<Vec<u8>> {
@@ -130,6 +438,239 @@ struct PersonForm {
     person: String,
 }
 
+/// Set or clear where a person is in a photo.
+fn set_region(context: Context, form: RegionForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    let region = if form.clear.is_some() {
+        None
+    } else {
+        match Region::new(form.x, form.y, form.w, form.h) {
+            Some(region) => Some(region),
+            None => return redirect_to_img(form.image),
+        }
+    };
+    let result =
+        Person::get_or_create_name(&db, &form.person).and_then(|person| {
+            Photo::set_region(&db, form.image, person.id, region)
+        });
+    match result {
+        Ok(()) => redirect_to_img(form.image),
+        Err(error) => {
+            warn!("Failed to set region in #{}: {}", form.image, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RegionForm {
+    image: i32,
+    person: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    w: f32,
+    #[serde(default)]
+    h: f32,
+    clear: Option<String>,
+}
+
+/// Choose a photo as the portrait of a person, or forget the choice.
+///
+/// The region of the person in the photo is used if known, otherwise
+/// the entire photo.
+fn set_portrait(context: Context, form: PortraitForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    use crate::schema::people::dsl::people;
+    let db = context.db().unwrap();
+    let person = match people.find(form.person).first::<Person>(&db) {
+        Ok(person) => person,
+        Err(_) => return not_found(&context),
+    };
+    let clear = form.clear.is_some();
+    let result = match (form.image, clear) {
+        (_, true) => Person::clear_portrait(&db, person.id),
+        (Some(image), false) => {
+            use crate::schema::photos::dsl::photos;
+            photos
+                .find(image)
+                .first::<Photo>(&db)
+                .and_then(|photo| photo.load_regions(&db))
+                .and_then(|regions| {
+                    let region = regions
+                        .into_iter()
+                        .find(|(p, _)| p.id == person.id)
+                        .map(|(_, region)| region)
+                        .unwrap_or(Region {
+                            x: 0.,
+                            y: 0.,
+                            w: 1.,
+                            h: 1.,
+                        });
+                    Person::set_portrait(&db, person.id, image, region)
+                })
+        }
+        (None, false) => return not_found(&context),
+    };
+    if let Err(error) = result {
+        warn!("Failed to set portrait of {:?}: {}", person, error);
+        return not_found(&context);
+    }
+    match form.image {
+        Some(image) if !clear => redirect_to_img(image),
+        _ => redirect(&format!("/person/{}", person.slug)),
+    }
+}
+
+#[derive(Deserialize)]
+struct PortraitForm {
+    person: i32,
+    image: Option<i32>,
+    clear: Option<String>,
+}
+
+fn set_caption(context: Context, form: CaptionForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    match Photo::set_caption(&db, form.image, &form.title, &form.description) {
+        Ok(photo) => redirect_to_img(photo.id),
+        Err(error) => {
+            warn!("Failed to set caption of #{}: {}", form.image, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CaptionForm {
+    image: i32,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+}
+
+fn add_comment(context: Context, form: CommentForm) -> Response<Vec<u8>> {
+    let user = match context.authorized_user() {
+        Some(user) => user,
+        None => return permission_denied(),
+    };
+    let body = form.body.trim();
+    if body.is_empty() {
+        return redirect_to_img(form.image);
+    }
+    let db = context.db().unwrap();
+    match Comment::add(&db, form.image, user, body) {
+        Ok(id) => redirect(&format!("/img/{}#comment{}", form.image, id)),
+        Err(error) => {
+            warn!("Failed to comment #{}: {}", form.image, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CommentForm {
+    image: i32,
+    body: String,
+}
+
+/// Remove a comment, as moderation.
+fn delete_comment(context: Context, form: IdForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    match Comment::delete(&context.db().unwrap(), form.id) {
+        Ok(photo) => {
+            info!("Deleted comment {} on #{}", form.id, photo);
+            redirect(&format!("/img/{}#comments", photo))
+        }
+        Err(error) => {
+            warn!("Failed to delete comment {}: {}", form.id, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IdForm {
+    id: i32,
+}
+
+fn toggle_favourite(context: Context, form: ImageForm) -> Response<Vec<u8>> {
+    let user = match context.authorized_user() {
+        Some(user) => user,
+        None => return permission_denied(),
+    };
+    let db = context.db().unwrap();
+    match Photo::toggle_favourite(&db, form.image, user) {
+        Ok(fav) => {
+            info!("Favourite #{} for {}: {}", form.image, user, fav);
+            redirect_to_img(form.image)
+        }
+        Err(error) => {
+            warn!("Failed to toggle favourite #{}: {}", form.image, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageForm {
+    image: i32,
+}
+
+fn set_date(context: Context, form: DateForm) -> Response<Vec<u8>> {
+    if !context.is_authorized() {
+        return permission_denied();
+    }
+    let db = context.db().unwrap();
+    let date = match form.copy.as_deref() {
+        Some(dir) => {
+            use crate::schema::photos::dsl::photos;
+            photos
+                .find(form.image)
+                .first::<Photo>(&db)
+                .and_then(|photo| photo.dated_neighbour(&db, dir == "prev"))
+                .ok()
+                .and_then(|neighbour| neighbour.and_then(|n| n.date))
+        }
+        None => clock_shift::parse_datetime(&form.date).ok(),
+    };
+    if let Some(date) = date {
+        info!("Should set date of #{} to {}", form.image, date);
+        match Photo::set_manual_date(&db, &[form.image], date) {
+            Ok(1) => return redirect_to_img(form.image),
+            Ok(_) => (),
+            Err(error) => {
+                warn!("Failed set date of image #{}: {}", form.image, error);
+            }
+        }
+    } else {
+        info!("No date to set for image #{}: {:?}", form.image, form);
+    }
+    not_found(&context)
+}
+
+/// Set the date of a photo, either to a given value or copied from
+/// the closest dated photo by path (`copy` is "prev" or "next").
+#[derive(Debug, Deserialize)]
+struct DateForm {
+    image: i32,
+    #[serde(default)]
+    date: String,
+    copy: Option<String>,
+}
+
 fn set_grade(context: Context, form: GradeForm) -> Response<Vec<u8>> {
     if !context.is_authorized() {
         return permission_denied();
@@ -166,6 +707,54 @@ struct GradeForm {
     grade: i16,
 }
 
+/// Set or clear the personal rating of a photo.
+fn set_rating(context: Context, form: RatingForm) -> Response<Vec<u8>> {
+    let user = match context.authorized_user() {
+        Some(user) => user,
+        None => return permission_denied(),
+    };
+    let grade = if form.clear.is_some() {
+        None
+    } else if (0..=100).contains(&form.grade) {
+        Some(form.grade)
+    } else {
+        return error_response(StatusCode::BAD_REQUEST);
+    };
+    let db = context.db().unwrap();
+    match Photo::set_rating(&db, form.image, user, grade) {
+        Ok(()) => redirect_to_img(form.image),
+        Err(error) => {
+            warn!("Failed to rate #{}: {}", form.image, error);
+            not_found(&context)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RatingForm {
+    image: i32,
+    grade: i16,
+    clear: Option<String>,
+}
+
+/// Choose if groups should be represented by personal ratings.
+fn set_ratings(context: Context, form: RatingsForm) -> Response<Vec<u8>> {
+    let user = match context.authorized_user() {
+        Some(user) => user,
+        None => return permission_denied(),
+    };
+    let db = context.db().unwrap();
+    if let Err(error) = set_own_ratings(&db, user, form.own.is_some()) {
+        warn!("Failed to set rating preference of {}: {}", user, error);
+    }
+    redirect("/favourites")
+}
+
+#[derive(Deserialize)]
+struct RatingsForm {
+    own: Option<String>,
+}
+
 fn set_location(context: Context, form: CoordForm) -> Response<Vec<u8>> {
     if !context.is_authorized() {
         return permission_denied();
@@ -179,17 +768,27 @@ fn set_location(context: Context, form: CoordForm) -> Response<Vec<u8>> {
     use diesel::insert_into;
     let db = context.db().unwrap();
     insert_into(positions)
-        .values((photo_id.eq(image), latitude.eq(lat), longitude.eq(lng)))
+        .values((
+            photo_id.eq(image),
+            latitude.eq(lat),
+            longitude.eq(lng),
+            source.eq("manual"),
+        ))
         .on_conflict(photo_id)
         .do_update()
-        .set((latitude.eq(lat), longitude.eq(lng)))
+        .set((latitude.eq(lat), longitude.eq(lng), source.eq("manual")))
         .execute(&db)
         .expect("Insert image position");
 
     match context.overpass().update_image_places(&db, form.image) {
         Ok(()) => (),
         // TODO Tell the user something failed?
-        Err(err) => warn!("Failed to fetch places: {:?}", err),
+        Err(err) => {
+            warn!("Failed to fetch places: {}", err);
+            if let Err(err) = fetch_places::enqueue(&db, form.image) {
+                warn!("Failed to queue #{} for places: {}", form.image, err);
+            }
+        }
     }
     redirect_to_img(form.image)
 }
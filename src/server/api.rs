@@ -1,7 +1,9 @@
 //! API views
 use super::login::LoginForm;
 use super::Context;
-use crate::models::{Photo, SizeTag};
+use crate::adm::clock_shift::parse_datetime;
+use crate::models::{Photo, SavedSearch, SizeTag};
+use crate::xmp::{faces_xmp, Face};
 use diesel::{self, prelude::*, result::Error as DbError, update};
 use log::warn;
 use serde::{Deserialize, Serialize};
@@ -14,7 +16,7 @@ type ApiResult<T> = Result<T, ApiError>;
 
 pub fn routes(s: BoxedFilter<(Context,)>) -> BoxedFilter<(impl Reply,)> {
     use warp::filters::method::v2::{get, post};
-    use warp::path::{end, path};
+    use warp::path::{end, param, path};
     use warp::{body, query};
     let login = path("login")
         .and(end())
@@ -30,9 +32,69 @@ pub fn routes(s: BoxedFilter<(Context,)>) -> BoxedFilter<(impl Reply,)> {
         .and(s.clone())
         .and(body::json())
         .map(make_public);
+    let pdate = path("date")
+        .and(end())
+        .and(post())
+        .and(s.clone())
+        .and(body::json())
+        .map(set_date)
+        .map(w);
+    let pcaption = path("caption")
+        .and(end())
+        .and(post())
+        .and(s.clone())
+        .and(body::json())
+        .map(set_caption)
+        .map(w);
+    let gxmp = path("xmp")
+        .and(end())
+        .and(get())
+        .and(s.clone())
+        .and(query())
+        .map(get_xmp)
+        .map(|result: ApiResult<Response>| {
+            result.unwrap_or_else(|err| err.into_response())
+        });
+    let map = path("map")
+        .and(end())
+        .and(get())
+        .and(s.clone())
+        .and(query())
+        .map(map_clusters)
+        .map(w);
+
+    let saved = path("search")
+        .and(end())
+        .and(get())
+        .and(s.clone())
+        .map(saved_searches)
+        .map(w);
+    let saved_one = path("search")
+        .and(param())
+        .and(end())
+        .and(get())
+        .and(s.clone())
+        .map(saved_search)
+        .map(w);
 
     login
-        .or(path("image").and(gimg.or(pimg).unify().map(w)))
+        .or(map)
+        .unify()
+        .or(saved)
+        .unify()
+        .or(saved_one)
+        .unify()
+        .or(path("image").and(
+            gimg.or(pimg)
+                .unify()
+                .map(w)
+                .or(pdate)
+                .unify()
+                .or(pcaption)
+                .unify()
+                .or(gxmp)
+                .unify(),
+        ))
         .boxed()
 }
 
@@ -106,6 +168,35 @@ fn get_img(context: Context, q: ImgQuery) -> ApiResult<GetImgResult> {
     Ok(GetImgResult::for_img(&img))
 }
 
+/// The known face regions of an image, as an xmp document.
+fn get_xmp(context: Context, q: ImgQuery) -> ApiResult<Response> {
+    let id = q.validate().map_err(ApiError::bad_request)?;
+    let db = context.db()?;
+    let img = id.load(&db)?.ok_or(NOT_FOUND)?;
+    if !context.is_authorized() && !img.is_public() {
+        return Err(NOT_FOUND);
+    }
+    let faces = img
+        .load_regions(&db)?
+        .into_iter()
+        .map(|(person, region)| {
+            let (x, y, w, h) = region.to_mwg(img.rotation);
+            Face {
+                name: person.person_name,
+                x,
+                y,
+                w,
+                h,
+            }
+        })
+        .collect::<Vec<_>>();
+    let xmp = faces_xmp(&faces, img.width, img.height);
+    Ok(
+        warp::reply::with_header(xmp, "content-type", "application/rdf+xml")
+            .into_response(),
+    )
+}
+
 fn make_public(context: Context, q: ImgQuery) -> ApiResult<GetImgResult> {
     if !context.is_authorized() {
         return Err(ApiError {
@@ -123,6 +214,205 @@ fn make_public(context: Context, q: ImgQuery) -> ApiResult<GetImgResult> {
     Ok(GetImgResult::for_img(&img))
 }
 
+/// Set the date of one or more photos.
+///
+/// Either `date` (like 2019-07-14T08:30:00) or `copy_from` (the id
+/// of a photo to copy the date from) should be given.
+#[derive(Debug, Deserialize)]
+struct SetDate {
+    ids: Vec<u32>,
+    date: Option<String>,
+    copy_from: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct SetDateResult {
+    date: String,
+    updated: usize,
+}
+
+fn set_date(context: Context, q: SetDate) -> ApiResult<SetDateResult> {
+    if !context.is_authorized() {
+        return Err(ApiError {
+            code: StatusCode::UNAUTHORIZED,
+            msg: "Authorization required",
+        });
+    }
+    let db = context.db()?;
+    let date = match (q.date, q.copy_from) {
+        (Some(date), None) => parse_datetime(&date)
+            .map_err(|_| ApiError::bad_request("bad date"))?,
+        (None, Some(id)) => ImgIdentifier::Id(id)
+            .load(&db)?
+            .ok_or(NOT_FOUND)?
+            .date
+            .ok_or_else(|| ApiError::bad_request("no date to copy"))?,
+        (None, None) => {
+            return Err(ApiError::bad_request("date or copy_from required"))
+        }
+        (Some(_), Some(_)) => {
+            return Err(ApiError::bad_request("Conflicting arguments"))
+        }
+    };
+    let ids = q.ids.iter().map(|id| *id as i32).collect::<Vec<_>>();
+    let updated = Photo::set_manual_date(&db, &ids, date)?;
+    Ok(SetDateResult {
+        date: date.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        updated,
+    })
+}
+
+/// Set the title and/or description of a photo.
+///
+/// A field that is not given is left as it is, an empty string
+/// clears it.
+#[derive(Debug, Deserialize)]
+struct SetCaption {
+    #[serde(flatten)]
+    photo: ImgQuery,
+    title: Option<String>,
+    description: Option<String>,
+}
+
+fn set_caption(context: Context, q: SetCaption) -> ApiResult<GetImgResult> {
+    if !context.is_authorized() {
+        return Err(ApiError {
+            code: StatusCode::UNAUTHORIZED,
+            msg: "Authorization required",
+        });
+    }
+    let id = q.photo.validate().map_err(ApiError::bad_request)?;
+    let db = context.db()?;
+    let img = id.load(&db)?.ok_or(NOT_FOUND)?;
+    let title = q.title.or(img.title).unwrap_or_default();
+    let description = q.description.or(img.description).unwrap_or_default();
+    let img = Photo::set_caption(&db, img.id, &title, &description)?;
+    Ok(GetImgResult::for_img(&img))
+}
+
+/// A bounding box and a zoom level, as used by leaflet.
+#[derive(Debug, Deserialize)]
+struct MapQuery {
+    south: f64,
+    west: f64,
+    north: f64,
+    east: f64,
+    zoom: u8,
+}
+
+/// A group of photos close to each other.
+///
+/// The position is the average of the photo positions, and `photo`
+/// is the id of a photo to show for the group.
+#[derive(Debug, Serialize)]
+struct MapCluster {
+    lat: f64,
+    lng: f64,
+    count: i64,
+    photo: i32,
+}
+
+/// Get photos within a bounding box, clustered in a grid that
+/// depends on the zoom level.
+fn map_clusters(context: Context, q: MapQuery) -> ApiResult<Vec<MapCluster>> {
+    use crate::schema::photos::dsl as p;
+    use crate::schema::positions::dsl as pos;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{BigInt, Integer};
+    let db = context.db()?;
+    let micro = |v: f64, max: f64| (v.max(-max).min(max) * 1e6) as i32;
+    // A map tile is 256 pixels wide, use cells of about 64 pixels.
+    let cell = (360_000_000 >> q.zoom.min(24)) / 4 + 1;
+    let cell_expr = format!(
+        "(latitude + 90000000) / {cell}, (longitude + 180000000) / {cell}",
+        cell = cell,
+    );
+    Ok(pos::positions
+        .filter(
+            pos::photo_id
+                .eq_any(Photo::query(context.is_authorized()).select(p::id)),
+        )
+        .filter(
+            pos::latitude.between(micro(q.south, 90.), micro(q.north, 90.)),
+        )
+        .filter(
+            pos::longitude.between(micro(q.west, 180.), micro(q.east, 180.)),
+        )
+        .select(sql::<(Integer, Integer, BigInt, Integer)>(
+            "avg(latitude)::int, avg(longitude)::int, count(*), max(photo_id)",
+        ))
+        .group_by(sql::<(Integer, Integer)>(&cell_expr))
+        .limit(5000)
+        .load::<(i32, i32, i64, i32)>(&db)?
+        .into_iter()
+        .map(|(lat, lng, count, photo)| MapCluster {
+            lat: f64::from(lat) / 1e6,
+            lng: f64::from(lng) / 1e6,
+            count,
+            photo,
+        })
+        .collect())
+}
+
+/// A saved search, and the photos currently matching it (if
+/// requested by slug).
+#[derive(Debug, Serialize)]
+struct SavedSearchInfo {
+    slug: String,
+    name: String,
+    public: bool,
+    query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photos: Option<Vec<SearchHit>>,
+}
+
+impl SavedSearchInfo {
+    fn new(saved: SavedSearch, photos: Option<Vec<SearchHit>>) -> Self {
+        SavedSearchInfo {
+            slug: saved.slug,
+            name: saved.name,
+            public: saved.is_public,
+            query: saved.query,
+            photos,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SearchHit {
+    id: i32,
+    small: ImgLink,
+}
+
+/// List the saved searches visible to the user.
+fn saved_searches(context: Context) -> ApiResult<Vec<SavedSearchInfo>> {
+    let db = context.db()?;
+    Ok(SavedSearch::list(&db, context.is_authorized())?
+        .into_iter()
+        .map(|saved| SavedSearchInfo::new(saved, None))
+        .collect())
+}
+
+/// Get a saved search with its matching photos.
+fn saved_search(slug: String, context: Context) -> ApiResult<SavedSearchInfo> {
+    let db = context.db()?;
+    let saved = SavedSearch::by_slug(&slug, &db, context.is_authorized())
+        .optional()?
+        .ok_or(NOT_FOUND)?;
+    let photos = super::search::saved_photos(&context, &db, &saved)
+        .map_err(|e| {
+            warn!("Failed to search {:?}: {}", slug, e);
+            ApiError::bad_request("bad saved search")
+        })?
+        .iter()
+        .map(|img| SearchHit {
+            id: img.id,
+            small: ImgLink::new(img, SizeTag::Small),
+        })
+        .collect();
+    Ok(SavedSearchInfo::new(saved, Some(photos)))
+}
+
 struct ApiError {
     code: StatusCode,
     msg: &'static str,
@@ -175,6 +465,10 @@ struct GetImgResult {
     small: ImgLink,
     medium: ImgLink,
     public: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
 }
 
 impl GetImgResult {
@@ -183,6 +477,8 @@ impl GetImgResult {
             small: ImgLink::new(img, SizeTag::Small),
             medium: ImgLink::new(img, SizeTag::Medium),
             public: img.is_public,
+            title: img.title.clone(),
+            description: img.description.clone(),
         }
     }
 }
@@ -1,6 +1,6 @@
 use super::render_ructe::RenderRucte;
 use super::{error_response, not_found, Context};
-use crate::models::{Photo, SizeTag};
+use crate::models::{Facet, Person, Photo, Portrait, SizeTag};
 use diesel::prelude::*;
 use std::str::FromStr;
 use warp::http::{header, Response, StatusCode};
@@ -52,6 +52,27 @@ pub fn show_image(img: ImgName, context: Context) -> Response<Vec<u8>> {
     not_found(&context)
 }
 
+/// A square thumbnail of the portrait of a person.
+pub fn person_face(context: Context, slug: String) -> Response<Vec<u8>> {
+    let db = context.db().unwrap();
+    let portrait = Person::by_slug(&slug, &db)
+        .and_then(|person| person.portrait(&db, context.is_authorized()));
+    if let Ok(Some(Portrait { photo, region, .. })) = portrait {
+        let size = SizeTag::Small;
+        let data = context
+            .cached_or(&photo.region_cache_key(&region, size), || {
+                context.photos().scale_region(&photo, region, size.px())
+            })
+            .expect("Get face image data");
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime::IMAGE_JPEG.as_ref())
+            .body(data)
+            .unwrap();
+    }
+    not_found(&context)
+}
+
 /// A client-side / url file name for a file.
 /// Someting like 4711-s.jpg
 #[derive(Debug, Eq, PartialEq)]
@@ -16,12 +16,13 @@ pub use self::photolink::PhotoLink;
 use self::render_ructe::RenderRucte;
 use self::search::*;
 use self::splitlist::*;
+pub use self::splitlist::{Pager, Sort};
 use self::views_by_category::*;
 use self::views_by_date::*;
 use super::{CacheOpt, DbOpt, DirOpt};
 use crate::adm::result::Error;
 use crate::fetch_places::OverpassOpt;
-use crate::models::Photo;
+use crate::models::{uses_own_ratings, Comment, Photo, Place};
 use crate::pidfiles::handle_pid_file;
 use crate::templates::{self, Html};
 use chrono::Datelike;
@@ -87,24 +88,30 @@ pub fn run(args: &Args) -> Result<(), Error> {
         .or(get().and(end()).and(s()).map(all_years))
         .or(get().and(path("img")).and(param()).and(end()).and(s()).map(photo_details))
         .or(get().and(path("img")).and(param()).and(end()).and(s()).map(image::show_image))
-        .or(get().and(path("0")).and(end()).and(s()).map(all_null_date))
+        .or(get().and(path("0")).and(end()).and(s()).and(query()).map(all_null_date))
         .or(get().and(param()).and(end()).and(s()).map(months_in_year))
         .or(get().and(param()).and(param()).and(end()).and(s()).map(days_in_month))
         .or(get().and(param()).and(param()).and(param()).and(end()).and(query()).and(s()).map(all_for_day))
         .or(get().and(path("person")).and(end()).and(s()).map(person_all))
         .or(get().and(path("person")).and(s()).and(param()).and(end()).and(query()).map(person_one))
+        .or(get().and(path("person")).and(s()).and(param()).and(path("face.jpg")).and(end()).map(image::person_face))
         .or(get().and(path("place")).and(end()).and(s()).map(place_all))
         .or(get().and(path("place")).and(s()).and(param()).and(end()).and(query()).map(place_one))
         .or(get().and(path("tag")).and(end()).and(s()).map(tag_all))
         .or(get().and(path("tag")).and(s()).and(param()).and(end()).and(query()).map(tag_one))
+        .or(get().and(path("map")).and(end()).and(s()).map(map_page))
         .or(get().and(path("random")).and(end()).and(s()).map(random_image))
         .or(get().and(path("thisday")).and(end()).and(s()).map(on_this_day))
+        .or(get().and(path("activity")).and(end()).and(s()).map(recent_activity))
+        .or(get().and(path("favourites")).and(end()).and(s()).and(query()).map(my_favourites))
+        .or(get().and(path("slideshow")).and(end()).and(s()).and(query()).map(slideshow))
         .or(get().and(path("next")).and(end()).and(s()).and(query()).map(next_image))
         .or(get().and(path("prev")).and(end()).and(s()).and(query()).map(prev_image))
         .or(get().and(path("ac")).and(end()).and(s()).and(query()).map(auto_complete_any))
         .or(get().and(path("ac")).and(path("tag")).and(s()).and(query()).map(auto_complete_tag))
         .or(get().and(path("ac")).and(path("person")).and(s()).and(query()).map(auto_complete_person))
         .or(get().and(path("search")).and(end()).and(s()).and(query()).map(search))
+        .or(get().and(path("search")).and(param()).and(end()).and(s()).and(query()).map(saved_search))
         .or(path("api").and(api::routes(s())))
         .or(path("adm").and(admin::routes(s())));
     warp::serve(routes.recover(customize_error)).run(args.listen);
@@ -179,6 +186,11 @@ fn static_file(name: Tail) -> Result<impl Reply, Rejection> {
     }
 }
 
+/// A map of all photos, populated from the api by javascript.
+fn map_page(context: Context) -> Response<Vec<u8>> {
+    Response::builder().html(|o| templates::map(o, &context))
+}
+
 fn random_image(context: Context) -> Response<Vec<u8>> {
     use crate::schema::photos::dsl::id;
     use diesel::expression::dsl::sql;
@@ -196,6 +208,110 @@ fn random_image(context: Context) -> Response<Vec<u8>> {
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct SlideshowQuery {
+    /// The listing or search to show, as a local url.
+    of: String,
+    /// Seconds to show each photo.
+    #[serde(default = "default_interval")]
+    interval: u32,
+    /// Show the photos in random order.
+    #[serde(default)]
+    shuffle: bool,
+}
+
+fn default_interval() -> u32 {
+    5
+}
+
+/// Show the photos of any listing, one at a time.
+///
+/// The photos are fetched from the json windows of the listing, so
+/// the same visibility rules apply as for the listing itself.
+fn slideshow(context: Context, query: SlideshowQuery) -> Response<Vec<u8>> {
+    if !is_local_url(&query.of) {
+        return not_found(&context);
+    }
+    let (path, params) = match query.of.find('?') {
+        Some(i) => (&query.of[..i], &query.of[i + 1..]),
+        None => (&query.of[..], ""),
+    };
+    let mut params =
+        serde_urlencoded::from_str::<Vec<(String, String)>>(params)
+            .unwrap_or_default();
+    let window = ["after", "before", "json"];
+    let order = ["sort", "seed"];
+    let dropped =
+        |k: &str| window.contains(&k) || (query.shuffle && order.contains(&k));
+    params.retain(|(k, _)| !dropped(k));
+    if query.shuffle {
+        params.push(("sort".into(), Sort::Random.value().into()));
+    }
+    params.push(("json".into(), "true".into()));
+    let src = format!(
+        "{}?{}",
+        path,
+        serde_urlencoded::to_string(&params).unwrap_or_default(),
+    );
+    let interval = query.interval.max(1);
+    Response::builder().html(|o| {
+        templates::slideshow(
+            o,
+            &context,
+            &query.of,
+            &src,
+            interval,
+            query.shuffle,
+        )
+    })
+}
+
+/// True if `url` is a path on this site.
+///
+/// Browsers treat a backslash like a slash, so `/\host` is another
+/// site, just like `//host`.
+fn is_local_url(url: &str) -> bool {
+    url.starts_with('/')
+        && !url.starts_with("//")
+        && !url.contains('\\')
+        && !url.contains(char::is_control)
+}
+
+/// The latest comments, on photos visible to the user.
+fn recent_activity(context: Context) -> Response<Vec<u8>> {
+    let db = context.db().unwrap();
+    let comments = Comment::recent(&db, context.is_authorized(), 50).unwrap();
+    Response::builder().html(|o| templates::activity(o, &context, &comments))
+}
+
+/// The favourite photos of the logged in user.
+fn my_favourites(context: Context, range: ImgRange) -> Response<Vec<u8>> {
+    use crate::schema::favourites::dsl as f;
+    use crate::schema::photos::dsl::id;
+    use crate::schema::users::dsl as u;
+    let user = match context.authorized_user() {
+        Some(user) => user,
+        None => return redirect("/login?next=/favourites"),
+    };
+    let db = context.db().unwrap();
+    let photos = Photo::query(true).filter(
+        id.eq_any(
+            f::favourites
+                .inner_join(u::users)
+                .filter(u::username.eq(user))
+                .select(f::photo_id),
+        ),
+    );
+    let (links, coords, pager) = links_by_time(&context, photos, range, true);
+    if range.json {
+        return pager.json(&links);
+    }
+    let own = uses_own_ratings(&db, user).unwrap_or(false);
+    Response::builder().html(|o| {
+        templates::favourites(o, &context, &links, &coords, &pager, own)
+    })
+}
+
 fn photo_details(id: i32, context: Context) -> Response<Vec<u8>> {
     use crate::schema::photos::dsl::photos;
     let c = context.db().unwrap();
@@ -218,11 +334,19 @@ fn photo_details(id: i32, context: Context) -> Response<Vec<u8>> {
                         })
                         .unwrap_or_default(),
                     &tphoto.load_people(&c).unwrap(),
+                    &tphoto.load_regions(&c).unwrap(),
                     &tphoto.load_places(&c).unwrap(),
                     &tphoto.load_tags(&c).unwrap(),
                     &tphoto.load_position(&c),
                     &tphoto.load_attribution(&c),
                     &tphoto.load_camera(&c),
+                    &tphoto.load_lens(&c),
+                    &Comment::for_photo(&c, tphoto.id).unwrap(),
+                    &tphoto.load_favourited_by(&c).unwrap(),
+                    context
+                        .authorized_user()
+                        .and_then(|u| tphoto.load_rating(&c, u).ok())
+                        .flatten(),
                     &tphoto,
                 )
             });
@@ -259,6 +383,13 @@ impl Link {
             monthname(month),
         ))
     }
+    fn place(place: &Place) -> Self {
+        Html(format!(
+            "<a href='/place/{}'>{}</a>",
+            place.slug,
+            place.place_name.replace('&', "&amp;").replace('<', "&lt;"),
+        ))
+    }
     fn prev(from: i32) -> Self {
         Html(format!(
             "<a href='/prev?from={}' title='Previous image (by time)'>\
@@ -275,8 +406,58 @@ impl Link {
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
 pub struct ImgRange {
     pub from: Option<i32>,
     pub to: Option<i32>,
+    /// Show the window of photos after this photo.
+    pub after: Option<i32>,
+    /// Show the window of photos before this photo.
+    pub before: Option<i32>,
+    /// Get the window as json, rather than html.
+    #[serde(default)]
+    pub json: bool,
+    #[serde(default)]
+    pub sort: Sort,
+    /// Seed for a random order.
+    pub seed: Option<u32>,
+}
+
+impl ImgRange {
+    /// A link to a window of this range, starting at a key photo.
+    fn href(&self, path: &str, key: &str, photo: i32) -> String {
+        format!("{}?{}{}={}", path, self.query(), key, photo)
+    }
+
+    /// A link to a slideshow of this range.
+    fn slideshow(&self, path: &str) -> String {
+        let of = format!("{}?{}", path, self.query());
+        format!("/slideshow?of={}", url_encode(of.trim_end_matches('&')))
+    }
+
+    /// The query parameters of this range, each followed by a `&`.
+    fn query(&self) -> String {
+        let from = self.from.map(|v| format!("from={}&", v));
+        let to = self.to.map(|v| format!("to={}&", v));
+        let sort = Some(self.sort)
+            .filter(|s| *s != Sort::default())
+            .map(|s| format!("sort={}&", s.value()));
+        let seed = self.seed.map(|v| format!("seed={}&", v));
+        format!(
+            "{}{}{}{}",
+            from.unwrap_or_default(),
+            to.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            seed.unwrap_or_default(),
+        )
+    }
+}
+
+#[test]
+fn local_urls() {
+    assert!(is_local_url("/search/?q=beach&sort=oldest"));
+    assert!(!is_local_url("https://evil.example/"));
+    assert!(!is_local_url("//evil.example/"));
+    assert!(!is_local_url("/\\evil.example/"));
+    assert!(!is_local_url("/\t/evil.example/"));
 }
@@ -1,6 +1,8 @@
 use crate::models::{Photo, SizeTag};
 use chrono::Datelike;
+use serde::Serialize;
 
+#[derive(Serialize)]
 pub struct PhotoLink {
     pub title: Option<String>,
     pub href: String,
@@ -1,27 +1,40 @@
 use super::render_ructe::RenderRucte;
 use super::views_by_category::AcQ;
-use super::{links_by_time, Context, ImgRange};
+use super::{links_by_time, not_found, Context, ImgRange, PhotoLink};
 use crate::adm::result::Error;
-use crate::models::{Facet, Person, Photo, Place, Tag};
+use crate::models::{
+    Camera, Facet, Lens, Person, Photo, Place, SavedSearch, Tag,
+};
+use crate::schema::cameras::dsl as c;
 use crate::schema::people::dsl as h; // h as in human
 use crate::schema::photo_people::dsl as pp;
 use crate::schema::photo_places::dsl as pl;
 use crate::schema::photo_tags::dsl as pt;
+use crate::schema::photos;
 use crate::schema::photos::dsl as p;
 use crate::schema::places::dsl as l;
 use crate::schema::tags::dsl as t;
 use crate::templates;
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-use diesel::pg::PgConnection;
+use diesel::dsl::sql;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Double, Float, Integer, Text};
 use log::warn;
 use serde::Serialize;
+use slug::slugify;
+use std::fmt::{self, Display};
+use std::str::FromStr;
 use warp::http::Response;
 use warp::{reply, Reply};
 
+mod query;
+use self::query::{Kind, Term};
+
 #[derive(Debug, Serialize)]
 struct SearchTag {
-    /// Kind (may be "p" for person, "t" for tag, "l" for location).
+    /// Kind (may be "p" for person, "t" for tag, "l" for location,
+    /// or "c" for camera).
     k: char,
     /// Title of the the tag
     t: String,
@@ -104,14 +117,159 @@ pub fn auto_complete_any(context: Context, query: AcQ) -> impl Reply {
             .into_iter()
             .map(|(t, s)| SearchTag { k: 'l', t, s })
     });
+    tags.extend({
+        let query = c::cameras
+            .select((c::model, c::slug))
+            .filter(c::model.ilike(&qs).or(c::manufacturer.ilike(&qs)))
+            .into_boxed();
+        let query =
+            if context.is_authorized() {
+                query
+            } else {
+                query.filter(c::id.nullable().eq_any(
+                    p::photos.select(p::camera_id).filter(p::is_public),
+                ))
+            };
+        query
+            .order(c::model)
+            .limit(10)
+            .load::<(String, String)>(&db)
+            .unwrap()
+            .into_iter()
+            .map(|(t, s)| SearchTag { k: 'c', t, s })
+    });
     reply::json(&tags)
 }
 
 pub fn search(context: Context, query: Vec<(String, String)>) -> impl Reply {
+    let (window, query): (Vec<_>, Vec<_>) =
+        query.into_iter().partition(|(k, _)| {
+            ["after", "before", "json", "sort", "seed"].contains(&k.as_str())
+        });
+    let range = serde_urlencoded::to_string(&window)
+        .ok()
+        .and_then(|w| serde_urlencoded::from_str::<ImgRange>(&w).ok())
+        .unwrap_or_default();
+    let raw = serde_urlencoded::to_string(&query).unwrap_or_default();
+    let query = SearchQuery::load(query, &context.db().unwrap()).unwrap();
+    search_page(&context, &query, range, None, &raw, None)
+}
+
+/// Show a saved search.
+pub fn saved_search(
+    slug: String,
+    context: Context,
+    range: ImgRange,
+) -> Response<Vec<u8>> {
+    let db = context.db().unwrap();
+    let saved = match SavedSearch::by_slug(&slug, &db, context.is_authorized())
+    {
+        Ok(saved) => saved,
+        Err(_) => return not_found(&context),
+    };
+    let query = SearchQuery::load_saved(&saved, &db).unwrap();
+    search_page(&context, &query, range, Some(&saved), &saved.query, None)
+}
+
+/// Get the photos of a saved search, by date.
+pub fn saved_photos(
+    context: &Context,
+    db: &PgConnection,
+    saved: &SavedSearch,
+) -> Result<Vec<Photo>, Error> {
+    let query = SearchQuery::load_saved(saved, db)?;
+    Ok(filtered(context, &query)
+        .order((Photo::instant_expr(), p::id))
+        .load(db)?)
+}
+
+/// Show the search page again for a search that could not be saved.
+pub fn save_failed(
+    context: &Context,
+    raw: &str,
+    error: &str,
+) -> Response<Vec<u8>> {
+    let query = match serde_urlencoded::from_str(raw) {
+        Ok(query) => query,
+        Err(_) => return not_found(context),
+    };
     let query = SearchQuery::load(query, &context.db().unwrap()).unwrap();
     let range = ImgRange::default();
+    search_page(context, &query, range, None, raw, Some(error))
+}
+
+/// Render the search page.
+///
+/// The `raw` query string is used to save the search, and
+/// `save_error` tells why saving it failed.
+fn search_page(
+    context: &Context,
+    query: &SearchQuery,
+    range: ImgRange,
+    saved: Option<&SavedSearch>,
+    raw: &str,
+    save_error: Option<&str>,
+) -> Response<Vec<u8>> {
+    let first_window = range.after.is_none() && range.before.is_none();
+    let best = if let (Some(tsquery), true) = (query.tsquery(), first_window) {
+        filtered(context, query)
+            .order(
+                sql::<Float>("ts_rank(search_doc, to_tsquery('simple', ")
+                    .bind::<Text, _>(tsquery)
+                    .sql("))")
+                    .desc(),
+            )
+            .limit(BEST_MATCHES)
+            .load::<Photo>(&context.db().unwrap())
+            .unwrap()
+            .iter()
+            .map(PhotoLink::date_title)
+            .collect()
+    } else {
+        vec![]
+    };
+    let photos = filtered(context, query);
+    let (mut links, coords, mut pager) =
+        links_by_time(context, photos, range, true);
+    if saved.is_none() {
+        let addendum = query.to_query_string();
+        for link in &mut links {
+            if link.href.starts_with("/search/?") {
+                link.href += &addendum;
+            }
+        }
+        pager.append(&addendum);
+    }
+    if range.json {
+        return pager.json(&links);
+    }
+    Response::builder().html(|o| {
+        templates::search(
+            o, context, query, saved, raw, save_error, &best, &links, &coords,
+            &pager,
+        )
+    })
+}
 
+/// Max number of best matches to show for a text search.
+const BEST_MATCHES: i64 = 12;
+
+/// Get the photos matching a search query.
+fn filtered<'a>(
+    context: &Context,
+    query: &SearchQuery,
+) -> photos::BoxedQuery<'a, Pg> {
     let mut photos = Photo::query(context.is_authorized());
+    if query.unparsed {
+        photos = photos.filter(sql::<Bool>("false"));
+    }
+    if let Some(tsquery) = query.tsquery() {
+        photos = photos.filter(
+            sql::<Bool>("search_doc @@ to_tsquery('simple', ")
+                .bind::<Text, _>(tsquery)
+                .sql(")"),
+        );
+    }
     if let Some(since) = query.since {
         photos = photos.filter(p::date.ge(since));
     }
@@ -121,7 +279,7 @@ pub fn search(context: Context, query: Vec<(String, String)>) -> impl Reply {
     for tag in &query.t {
         let ids = pt::photo_tags
             .select(pt::photo_id)
-            .filter(pt::tag_id.eq(tag.item.id));
+            .filter(pt::tag_id.eq_any(tag.ids()));
         photos = if tag.inc {
             photos.filter(p::id.eq_any(ids))
         } else {
@@ -131,7 +289,7 @@ pub fn search(context: Context, query: Vec<(String, String)>) -> impl Reply {
     for location in &query.l {
         let ids = pl::photo_places
             .select(pl::photo_id)
-            .filter(pl::place_id.eq(location.item.id));
+            .filter(pl::place_id.eq_any(location.ids()));
         photos = if location.inc {
             photos.filter(p::id.eq_any(ids))
         } else {
@@ -141,13 +299,41 @@ pub fn search(context: Context, query: Vec<(String, String)>) -> impl Reply {
     for person in &query.p {
         let ids = pp::photo_people
             .select(pp::photo_id)
-            .filter(pp::person_id.eq(person.item.id));
+            .filter(pp::person_id.eq_any(person.ids()));
         photos = if person.inc {
             photos.filter(p::id.eq_any(ids))
         } else {
             photos.filter(p::id.ne_all(ids))
         }
     }
+    for camera in &query.c {
+        let ids = camera.ids();
+        photos = if camera.inc {
+            photos.filter(p::camera_id.eq_any(ids))
+        } else {
+            photos.filter(p::camera_id.ne_all(ids).or(p::camera_id.is_null()))
+        };
+    }
+    for lens in &query.lens {
+        let ids = lens.ids();
+        photos = if lens.inc {
+            photos.filter(p::lens_id.eq_any(ids))
+        } else {
+            photos.filter(p::lens_id.ne_all(ids).or(p::lens_id.is_null()))
+        };
+    }
+    if let Some(min) = query.iso.min {
+        photos = photos.filter(p::iso.ge(min));
+    }
+    if let Some(max) = query.iso.max {
+        photos = photos.filter(p::iso.le(max));
+    }
+    if let Some(min) = query.focal.min {
+        photos = photos.filter(p::focal_length.ge(min));
+    }
+    if let Some(max) = query.focal.max {
+        photos = photos.filter(p::focal_length.le(max));
+    }
     if let Some(pos) = query.pos {
         use crate::schema::positions::dsl as pos;
         let pos_ids = pos::positions.select(pos::photo_id);
@@ -157,16 +343,49 @@ pub fn search(context: Context, query: Vec<(String, String)>) -> impl Reply {
             photos = photos.filter(p::id.ne_all(pos_ids));
         }
     }
-
-    let (mut links, coords) = links_by_time(&context, photos, range, true);
-    let addendum = query.to_query_string();
-    for link in &mut links {
-        if link.href.starts_with("/search/?") {
-            link.href += &addendum;
-        }
+    if let Some(area) = query.area {
+        photos = photos.filter(p::id.eq_any(area.photo_ids()));
+    }
+    if let Some(min) = query.grade.min {
+        photos = photos.filter(p::grade.ge(min));
+    }
+    if let Some(max) = query.grade.max {
+        photos = photos.filter(p::grade.le(max));
+    }
+    if let Some(public) = query.public {
+        photos = photos.filter(p::is_public.eq(public));
+    }
+    if let Some(orientation) = query.orientation {
+        // Width and height are as stored, before rotation.
+        let turned = || p::rotation.eq_any(vec![90, 270]);
+        let upright = || p::rotation.ne_all(vec![90, 270]);
+        photos = match orientation {
+            Orientation::Landscape => photos.filter(
+                turned()
+                    .and(p::height.gt(p::width))
+                    .or(upright().and(p::width.gt(p::height))),
+            ),
+            Orientation::Portrait => photos.filter(
+                turned()
+                    .and(p::width.gt(p::height))
+                    .or(upright().and(p::height.gt(p::width))),
+            ),
+            Orientation::Square => photos.filter(p::width.eq(p::height)),
+        };
+    }
+    for missing in &query.missing {
+        photos = match missing {
+            Missing::Grade => photos.filter(p::grade.is_null()),
+            Missing::Date => photos.filter(p::date.is_null()),
+            Missing::Tags => photos
+                .filter(p::id.ne_all(pt::photo_tags.select(pt::photo_id))),
+            Missing::People => photos
+                .filter(p::id.ne_all(pp::photo_people.select(pp::photo_id))),
+            Missing::Places => photos
+                .filter(p::id.ne_all(pl::photo_places.select(pl::photo_id))),
+        };
     }
-    Response::builder()
-        .html(|o| templates::search(o, &context, &query, &links, &coords))
+    photos
 }
 
 #[derive(Debug, Default)]
@@ -177,33 +396,339 @@ pub struct SearchQuery {
     pub p: Vec<Filter<Person>>,
     /// Places (locations)
     pub l: Vec<Filter<Place>>,
+    /// Cameras
+    pub c: Vec<Filter<Camera>>,
+    /// Lenses
+    pub lens: Vec<Filter<Lens>>,
+    /// ISO sensitivity
+    pub iso: NumRange<i32>,
+    /// Focal length, in mm
+    pub focal: NumRange<f32>,
+    /// Grade, 0 to 100
+    pub grade: NumRange<i16>,
+    /// Public (or not public) photos
+    pub public: Option<bool>,
+    /// Shape of the photo, as shown
+    pub orientation: Option<Orientation>,
+    /// Kinds of metadata that the photos should lack
+    pub missing: Vec<Missing>,
     pub since: Option<NaiveDateTime>,
     pub until: Option<NaiveDateTime>,
     pub pos: Option<bool>,
-    /// Query (free-text, don't know what to do)
+    /// Geographic area
+    pub area: Option<GeoArea>,
+    /// Query, as entered
     pub q: String,
+    /// Words from the query to search for (or exclude, if false)
+    pub words: Vec<(bool, String)>,
+    /// Alternatives from the query, where any should match (or none,
+    /// if false).  Each alternative is one or more words.
+    pub any_words: Vec<(bool, Vec<Vec<String>>)>,
+    /// Error from parsing the query
+    pub error: Option<String>,
+    /// True if `q` could not be parsed, so nothing should match.
+    pub unparsed: bool,
 }
 
+/// Photos with any of the items (or none of them, if not inc).
 #[derive(Debug)]
 pub struct Filter<T> {
     pub inc: bool,
-    pub item: T,
+    pub items: Vec<T>,
+    /// Slugs that was not found.  They are kept so that the filter
+    /// is shown, and an included filter with no items matches nothing.
+    pub unknown: Vec<String>,
+}
+
+/// An inclusive range, open in either or both ends.
+#[derive(Debug, Default)]
+pub struct NumRange<T> {
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+impl<T: Copy + Display> NumRange<T> {
+    fn to_query_string(&self, name: &str) -> String {
+        let min = self.min.map(|v| format!("&{}_min={}", name, v));
+        let max = self.max.map(|v| format!("&{}_max={}", name, v));
+        min.into_iter().chain(max).collect()
+    }
+}
+
+/// A geographic area to search within.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeoArea {
+    /// South, west, north and east edges, in degrees.
+    Bbox(f64, f64, f64, f64),
+    /// Latitude and longitude of the center, in degrees, and radius
+    /// in meters.
+    Near(f64, f64, f64),
+}
+
+/// The shape of a photo, after rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 3] = [
+        Orientation::Landscape,
+        Orientation::Portrait,
+        Orientation::Square,
+    ];
+
+    fn parse(val: &str) -> Option<Orientation> {
+        Orientation::ALL.iter().cloned().find(|o| o.value() == val)
+    }
+
+    /// The value of the query parameter for this orientation.
+    pub fn value(self) -> &'static str {
+        match self {
+            Orientation::Landscape => "landscape",
+            Orientation::Portrait => "portrait",
+            Orientation::Square => "square",
+        }
+    }
+}
+
+/// A kind of metadata that a photo may lack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Missing {
+    Grade,
+    Date,
+    Tags,
+    People,
+    Places,
+}
+
+impl Missing {
+    pub const ALL: [Missing; 5] = [
+        Missing::Grade,
+        Missing::Date,
+        Missing::Tags,
+        Missing::People,
+        Missing::Places,
+    ];
+
+    fn parse(val: &str) -> Option<Missing> {
+        Missing::ALL.iter().cloned().find(|m| m.value() == val)
+    }
+
+    /// The value of the query parameter for this kind.
+    pub fn value(self) -> &'static str {
+        match self {
+            Missing::Grade => "grade",
+            Missing::Date => "date",
+            Missing::Tags => "tags",
+            Missing::People => "people",
+            Missing::Places => "places",
+        }
+    }
+}
+
+/// Length of a degree of latitude, in meters.
+const METERS_PER_DEGREE: f64 = 111_320.;
+
+impl GeoArea {
+    fn parse(key: &str, val: &str) -> Option<GeoArea> {
+        let v = val
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>();
+        match (key, v.as_deref()) {
+            ("bbox", Some(&[s, w, n, e])) if s <= n && w <= e => {
+                Some(GeoArea::Bbox(s, w, n, e))
+            }
+            ("near", Some(&[lat, lng, r])) if r > 0. => {
+                Some(GeoArea::Near(lat, lng, r))
+            }
+            _ => {
+                warn!("Bad value for {:?}: {:?}", key, val);
+                None
+            }
+        }
+    }
+
+    /// The name of the query parameter for this area.
+    pub fn param(&self) -> &'static str {
+        match self {
+            GeoArea::Bbox(..) => "bbox",
+            GeoArea::Near(..) => "near",
+        }
+    }
+
+    /// The value of the query parameter for this area.
+    pub fn value(&self) -> String {
+        match self {
+            GeoArea::Bbox(s, w, n, e) => format!("{},{},{},{}", s, w, n, e),
+            GeoArea::Near(lat, lng, r) => format!("{},{},{}", lat, lng, r),
+        }
+    }
+
+    /// The bounding box of this area, in degrees.
+    fn bbox(&self) -> (f64, f64, f64, f64) {
+        match *self {
+            GeoArea::Bbox(s, w, n, e) => (s, w, n, e),
+            GeoArea::Near(lat, lng, r) => {
+                let dlat = r / METERS_PER_DEGREE;
+                let dlng = dlat / lat.to_radians().cos().max(0.01);
+                (lat - dlat, lng - dlng, lat + dlat, lng + dlng)
+            }
+        }
+    }
+
+    /// Select the ids of photos positioned in this area.
+    ///
+    /// The bounding box is matched by the positions_point_idx index,
+    /// and for a radius, the distance is checked on the (flat) earth.
+    fn photo_ids(
+        &self,
+    ) -> crate::schema::positions::BoxedQuery<'static, Pg, Integer> {
+        use crate::schema::positions::dsl as pos;
+        let (s, w, n, e) = self.bbox();
+        let in_box = sql::<Bool>("point(latitude, longitude) <@ box(point(")
+            .bind::<Double, _>(s * 1e6)
+            .sql(",")
+            .bind::<Double, _>(w * 1e6)
+            .sql("), point(")
+            .bind::<Double, _>(n * 1e6)
+            .sql(",")
+            .bind::<Double, _>(e * 1e6)
+            .sql("))");
+        let ids = pos::positions.select(pos::photo_id).filter(in_box);
+        match *self {
+            GeoArea::Bbox(..) => ids.into_boxed(),
+            GeoArea::Near(lat, lng, r) => {
+                let r = r / METERS_PER_DEGREE * 1e6;
+                let within = sql::<Bool>("(latitude - ")
+                    .bind::<Double, _>(lat * 1e6)
+                    .sql(")^2 + ((longitude - ")
+                    .bind::<Double, _>(lng * 1e6)
+                    .sql(") * ")
+                    .bind::<Double, _>(lat.to_radians().cos())
+                    .sql(")^2 <= ")
+                    .bind::<Double, _>(r * r);
+                ids.filter(within).into_boxed()
+            }
+        }
+    }
+}
+
+impl fmt::Display for GeoArea {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeoArea::Bbox(s, w, n, e) => {
+                write!(out, "{:.3}–{:.3}, {:.3}–{:.3}", s, n, w, e)
+            }
+            GeoArea::Near(lat, lng, r) => {
+                write!(out, "{:.0} m from {:.4}, {:.4}", r, lat, lng)
+            }
+        }
+    }
+}
+
+/// Parse a flag value, `t` for true or `!t` for false.
+fn parse_flag(key: &str, val: &str) -> Option<bool> {
+    match val {
+        "t" => Some(true),
+        "!t" => Some(false),
+        "" => None,
+        val => {
+            warn!("Bad value for {:?}: {:?}", key, val);
+            None
+        }
+    }
+}
+
+fn parse_num<T: FromStr>(key: &str, val: &str) -> Option<T> {
+    if val.is_empty() {
+        return None;
+    }
+    val.parse()
+        .map_err(|_| warn!("Bad value for {:?}: {:?}", key, val))
+        .ok()
 }
 
 impl<T: Facet> Filter<T> {
+    /// Load a filter from a query parameter value, like `!a|b`.
     fn load(val: &str, db: &PgConnection) -> Option<Filter<T>> {
-        let (inc, slug) = if val.starts_with('!') {
-            (false, &val[1..])
+        let (inc, slugs) = if let Some(slugs) = val.strip_prefix('!') {
+            (false, slugs)
         } else {
             (true, val)
         };
-        match T::by_slug(slug, db) {
-            Ok(item) => Some(Filter { inc, item }),
-            Err(err) => {
-                warn!("No filter {:?}: {:?}", slug, err);
-                None
+        Filter::load_slugs(inc, slugs.split('|'), db)
+    }
+
+    /// Load a filter from slugs.  Return None if there are no slugs.
+    fn load_slugs<'a>(
+        inc: bool,
+        slugs: impl Iterator<Item = &'a str>,
+        db: &PgConnection,
+    ) -> Option<Filter<T>> {
+        let mut items = Vec::new();
+        let mut unknown = Vec::new();
+        for slug in slugs.filter(|slug| !slug.is_empty()) {
+            match T::by_slug(slug, db) {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    warn!("No filter {:?}: {:?}", slug, err);
+                    unknown.push(slug.to_string());
+                }
             }
         }
+        if items.is_empty() && unknown.is_empty() {
+            None
+        } else {
+            Some(Filter {
+                inc,
+                items,
+                unknown,
+            })
+        }
+    }
+
+    /// The value of this filter as a query parameter.
+    pub fn value(&self) -> String {
+        let slugs = self.items.iter().map(Facet::slug);
+        let unknown = self.unknown.iter().map(String::as_str);
+        let slugs = slugs.chain(unknown).collect::<Vec<_>>();
+        format!("{}{}", if self.inc { "" } else { "!" }, slugs.join("|"))
+    }
+
+    /// The names of the items, for display.
+    pub fn names(&self) -> String {
+        let names = self.items.iter().map(Facet::name);
+        let unknown = self.unknown.iter().map(String::as_str);
+        let names = names.chain(unknown).collect::<Vec<_>>();
+        names.join(" or ")
+    }
+
+    fn ids(&self) -> Vec<i32> {
+        self.items.iter().map(Facet::id).collect()
+    }
+}
+
+/// Add a loaded filter to `filters`, noting any unknown slugs in
+/// `error`.
+fn add_filter<T>(
+    filters: &mut Vec<Filter<T>>,
+    error: &mut Option<String>,
+    what: &str,
+    loaded: Option<Filter<T>>,
+) {
+    if let Some(filter) = loaded {
+        if !filter.unknown.is_empty() {
+            let msg = format!("No {} {}", what, filter.unknown.join(" or "));
+            *error = Some(match error.take() {
+                Some(e) => format!("{}; {}", e, msg),
+                None => msg,
+            });
+        }
+        filters.push(filter);
     }
 }
 
@@ -230,37 +755,77 @@ impl SearchQuery {
         for (key, val) in query {
             match key.as_ref() {
                 "q" => {
-                    if val.contains("!pos") {
-                        result.pos = Some(false);
-                    } else if val.contains("pos") {
-                        result.pos = Some(true);
-                    }
-                    result.q = val;
+                    let terms = result.parse_q(val);
+                    result.add_terms(terms, db);
                 }
                 "t" => {
-                    if let Some(f) = Filter::load(&val, db) {
-                        result.t.push(f);
-                    }
+                    let loaded = Filter::load(&val, db);
+                    add_filter(&mut result.t, &mut result.error, "tag", loaded)
                 }
                 "p" => {
-                    if let Some(f) = Filter::load(&val, db) {
-                        result.p.push(f);
-                    }
+                    let loaded = Filter::load(&val, db);
+                    add_filter(
+                        &mut result.p,
+                        &mut result.error,
+                        "person",
+                        loaded,
+                    )
                 }
                 "l" => {
-                    if let Some(f) = Filter::load(&val, db) {
-                        result.l.push(f);
+                    let loaded = Filter::load(&val, db);
+                    add_filter(
+                        &mut result.l,
+                        &mut result.error,
+                        "place",
+                        loaded,
+                    )
+                }
+                "c" => {
+                    let loaded = Filter::load(&val, db);
+                    add_filter(
+                        &mut result.c,
+                        &mut result.error,
+                        "camera",
+                        loaded,
+                    )
+                }
+                "lens" => {
+                    let loaded = Filter::load(&val, db);
+                    add_filter(
+                        &mut result.lens,
+                        &mut result.error,
+                        "lens",
+                        loaded,
+                    )
+                }
+                "iso_min" => result.iso.min = parse_num(&key, &val),
+                "iso_max" => result.iso.max = parse_num(&key, &val),
+                "focal_min" => result.focal.min = parse_num(&key, &val),
+                "focal_max" => result.focal.max = parse_num(&key, &val),
+                "grade_min" => result.grade.min = parse_num(&key, &val),
+                "grade_max" => result.grade.max = parse_num(&key, &val),
+                "pos" => result.pos = parse_flag(&key, &val),
+                "public" => result.public = parse_flag(&key, &val),
+                "orientation" => {
+                    result.orientation = Orientation::parse(&val);
+                    if result.orientation.is_none() && !val.is_empty() {
+                        warn!("Bad value for {:?}: {:?}", key, val);
                     }
                 }
-                "pos" => {
-                    result.pos = match val.as_str() {
-                        "t" => Some(true),
-                        "!t" => Some(false),
-                        "" => None,
-                        val => {
-                            warn!("Bad value for \"pos\": {:?}", val);
-                            None
-                        }
+                "missing" => match Missing::parse(&val) {
+                    Some(m) if !result.missing.contains(&m) => {
+                        result.missing.push(m)
+                    }
+                    Some(_) => (),
+                    None => warn!("Bad value for {:?}: {:?}", key, val),
+                },
+                "bbox" | "near" => {
+                    // A negated area (from the search form) is
+                    // just removed.
+                    result.area = if val.is_empty() || val.starts_with('!') {
+                        None
+                    } else {
+                        GeoArea::parse(&key, &val)
                     }
                 }
                 "from" => {
@@ -280,32 +845,242 @@ impl SearchQuery {
         }
         Ok(result)
     }
-    fn to_query_string(&self) -> String {
-        fn or_bang(cond: bool) -> &'static str {
-            if cond {
-                ""
-            } else {
-                "!"
+
+    fn load_saved(
+        saved: &SavedSearch,
+        db: &PgConnection,
+    ) -> Result<Self, Error> {
+        let query = serde_urlencoded::from_str(&saved.query).map_err(|e| {
+            Error::Other(format!("Bad saved search {:?}: {}", saved.slug, e))
+        })?;
+        SearchQuery::load(query, db)
+    }
+
+    /// Parse `q` as the query language.
+    ///
+    /// If it can't be parsed, the error is kept and the query is
+    /// marked to match nothing, rather than everything.
+    fn parse_q(&mut self, q: String) -> Vec<Term> {
+        let terms = match query::parse(&q) {
+            Ok(terms) => terms,
+            Err(e) => {
+                warn!("Bad query {:?}: {}", q, e);
+                self.error = Some(e);
+                self.unparsed = true;
+                vec![]
+            }
+        };
+        self.q = q;
+        terms
+    }
+
+    /// Add terms parsed from the query language.
+    fn add_terms(&mut self, terms: Vec<Term>, db: &PgConnection) {
+        for term in terms {
+            match term {
+                Term::Facet { kind, inc, values } => {
+                    let slugs = values.iter().map(slugify).collect::<Vec<_>>();
+                    let slugs = slugs.iter().map(String::as_str);
+                    let error = &mut self.error;
+                    match kind {
+                        Kind::Tag => add_filter(
+                            &mut self.t,
+                            error,
+                            "tag",
+                            Filter::load_slugs(inc, slugs, db),
+                        ),
+                        Kind::Person => add_filter(
+                            &mut self.p,
+                            error,
+                            "person",
+                            Filter::load_slugs(inc, slugs, db),
+                        ),
+                        Kind::Place => add_filter(
+                            &mut self.l,
+                            error,
+                            "place",
+                            Filter::load_slugs(inc, slugs, db),
+                        ),
+                        Kind::Camera => add_filter(
+                            &mut self.c,
+                            error,
+                            "camera",
+                            Filter::load_slugs(inc, slugs, db),
+                        ),
+                        Kind::Lens => add_filter(
+                            &mut self.lens,
+                            error,
+                            "lens",
+                            Filter::load_slugs(inc, slugs, db),
+                        ),
+                    }
+                }
+                Term::Pos(inc) => self.pos = Some(inc),
+                Term::Word(inc, word) => {
+                    self.words.extend(search_words(&word).map(|w| (inc, w)))
+                }
+                Term::Words { inc, words } => {
+                    let alternatives = words
+                        .iter()
+                        .map(|w| search_words(w).collect::<Vec<_>>())
+                        .filter(|w| !w.is_empty())
+                        .collect::<Vec<_>>();
+                    if !alternatives.is_empty() {
+                        self.any_words.push((inc, alternatives));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The words as a postgres text search query, if any.
+    ///
+    /// Each word is used as a prefix, and all of them must match
+    /// (or not match, for excluded words).  Of a group of
+    /// alternatives, any must match (or none of them).
+    fn tsquery(&self) -> Option<String> {
+        let prefixes = |words: &[String]| {
+            let words = words.iter().map(|w| format!("{}:*", w));
+            words.collect::<Vec<_>>().join(" & ")
+        };
+        let terms = self
+            .words
+            .iter()
+            .map(|(inc, w)| format!("{}{}:*", or_bang(*inc), w))
+            .chain(self.any_words.iter().map(|(inc, alternatives)| {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|words| match words.len() {
+                        1 => prefixes(words),
+                        _ => format!("({})", prefixes(words)),
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}({})", or_bang(*inc), alternatives.join(" | "))
+            }))
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" & "))
+        }
+    }
+
+    /// The words of the query, in the query language.
+    pub fn text(&self) -> String {
+        self.text_tokens().join(" ")
+    }
+
+    /// The words of the query, as tokens of the query language.
+    ///
+    /// A query that could not be parsed is given back as entered.
+    fn text_tokens(&self) -> Vec<String> {
+        if self.unparsed {
+            return self.q.split_whitespace().map(String::from).collect();
+        }
+        let mut result = self
+            .words
+            .iter()
+            .map(|(inc, w)| format!("{}{}", or_bang(*inc), w))
+            .collect::<Vec<_>>();
+        for (inc, alternatives) in &self.any_words {
+            let n = result.len();
+            for (i, words) in alternatives.iter().enumerate() {
+                if i > 0 {
+                    result.push("OR".into());
+                }
+                result.push(match words.len() {
+                    1 => words[0].clone(),
+                    _ => format!("\"{}\"", words.join(" ")),
+                });
+            }
+            result[n] = format!("{}({}", or_bang(*inc), result[n]);
+            if let Some(last) = result.last_mut() {
+                last.push(')');
             }
         }
+        result
+    }
+    fn to_query_string(&self) -> String {
         self.t
             .iter()
-            .map(|v| format!("&t={}{}", or_bang(v.inc), v.item.slug))
+            .map(|v| format!("&t={}", url_encode(&v.value())))
             .chain(
                 self.l
                     .iter()
-                    .map(|v| format!("&l={}{}", or_bang(v.inc), v.item.slug)),
+                    .map(|v| format!("&l={}", url_encode(&v.value()))),
             )
             .chain(
                 self.p
                     .iter()
-                    .map(|v| format!("&p={}{}", or_bang(v.inc), v.item.slug)),
+                    .map(|v| format!("&p={}", url_encode(&v.value()))),
+            )
+            .chain(
+                self.c
+                    .iter()
+                    .map(|v| format!("&c={}", url_encode(&v.value()))),
+            )
+            .chain(
+                self.lens
+                    .iter()
+                    .map(|v| format!("&lens={}", url_encode(&v.value()))),
             )
             .chain(self.pos.map(|v| format!("&pos={}t", or_bang(v))))
+            .chain(self.public.map(|v| format!("&public={}t", or_bang(v))))
+            .chain(
+                self.orientation
+                    .map(|o| format!("&orientation={}", o.value())),
+            )
+            .chain(
+                self.missing
+                    .iter()
+                    .map(|m| format!("&missing={}", m.value())),
+            )
+            .chain(self.area.map(|a| format!("&{}={}", a.param(), a.value())))
+            .chain({
+                let words = self.text_tokens();
+                if words.is_empty() {
+                    None
+                } else {
+                    let words = words.iter().map(|w| url_encode(w));
+                    Some(format!("&q={}", words.collect::<Vec<_>>().join("+")))
+                }
+            })
+            .chain(Some(self.iso.to_query_string("iso")))
+            .chain(Some(self.focal.to_query_string("focal")))
+            .chain(Some(self.grade.to_query_string("grade")))
             .collect()
     }
 }
 
+fn or_bang(cond: bool) -> &'static str {
+    if cond {
+        ""
+    } else {
+        "!"
+    }
+}
+
+/// Split text into lowercase words of letters and digits.
+fn search_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Percent-encode anything but ascii letters, digits and slug
+/// punctuation.
+pub fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                char::from(b).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
 fn datetime_from_parts(
     date: Option<&str>,
     time: Option<&str>,
@@ -321,3 +1096,140 @@ fn datetime_from_parts(
             )
         })
 }
+
+#[test]
+fn parse_bbox() {
+    assert_eq!(
+        GeoArea::parse("bbox", "59.3,18.0,59.4,18.1"),
+        Some(GeoArea::Bbox(59.3, 18.0, 59.4, 18.1)),
+    );
+    assert_eq!(GeoArea::parse("bbox", "59.4,18.0,59.3,18.1"), None);
+}
+
+#[test]
+fn near_roundtrip() {
+    let area = GeoArea::Near(59.33, 18.06, 500.);
+    assert_eq!(GeoArea::parse(area.param(), &area.value()), Some(area));
+}
+
+#[test]
+fn near_bbox() {
+    let (s, w, n, e) = GeoArea::Near(60., 18., 1113.2).bbox();
+    assert!((n - s - 0.02).abs() < 1e-9);
+    assert!((e - w - 0.04).abs() < 1e-6);
+}
+
+#[test]
+fn text_query() {
+    let query = SearchQuery {
+        words: search_words("Kalle Anka, 1934")
+            .map(|w| (true, w))
+            .collect(),
+        ..SearchQuery::default()
+    };
+    assert_eq!(query.tsquery().unwrap(), "kalle:* & anka:* & 1934:*");
+}
+
+#[test]
+fn text_query_exclude() {
+    let query = SearchQuery {
+        words: vec![(true, "beach".into()), (false, "work".into())],
+        ..SearchQuery::default()
+    };
+    assert_eq!(query.tsquery().unwrap(), "beach:* & !work:*");
+    assert_eq!(query.to_query_string(), "&q=beach+%21work");
+}
+
+#[test]
+fn unknown_slugs_are_errors() {
+    let mut filters = Vec::new();
+    let mut error = None;
+    let filter = |inc, unknown: &[&str]| Filter::<Tag> {
+        inc,
+        items: vec![],
+        unknown: unknown.iter().map(|s| s.to_string()).collect(),
+    };
+    add_filter(&mut filters, &mut error, "tag", Some(filter(true, &["a"])));
+    add_filter(&mut filters, &mut error, "tag", Some(filter(false, &[])));
+    add_filter(&mut filters, &mut error, "tag", None);
+    add_filter(&mut filters, &mut error, "tag", Some(filter(false, &["b"])));
+    assert_eq!(error.as_deref(), Some("No tag a; No tag b"));
+    assert_eq!(filters.len(), 3);
+    assert_eq!(filters[0].value(), "a");
+    assert_eq!(filters[2].value(), "!b");
+}
+
+#[test]
+fn text_query_alternatives() {
+    let words = |w: &[&str]| w.iter().map(|w| w.to_string()).collect();
+    let query = SearchQuery {
+        words: vec![(false, "work".into())],
+        any_words: vec![(
+            true,
+            vec![
+                words(&["beach"]),
+                words(&["lake"]),
+                words(&["kalle", "anka"]),
+            ],
+        )],
+        ..SearchQuery::default()
+    };
+    assert_eq!(
+        query.tsquery().unwrap(),
+        "!work:* & (beach:* | lake:* | (kalle:* & anka:*))"
+    );
+    assert_eq!(query.text(), "!work (beach OR lake OR \"kalle anka\")");
+    assert_eq!(
+        query::parse(&query.text()).unwrap()[1],
+        Term::Words {
+            inc: true,
+            words: words(&["beach", "lake", "kalle anka"]),
+        }
+    );
+    assert_eq!(
+        query.to_query_string(),
+        "&q=%21work+%28beach+OR+lake+OR+%22kalle%20anka%22%29"
+    );
+}
+
+#[test]
+fn text_query_string() {
+    let query = SearchQuery {
+        words: search_words("Humlegården").map(|w| (true, w)).collect(),
+        ..SearchQuery::default()
+    };
+    assert_eq!(query.to_query_string(), "&q=humleg%C3%A5rden");
+}
+
+#[test]
+fn curation_query_string() {
+    let query = SearchQuery {
+        grade: NumRange {
+            min: Some(80),
+            max: None,
+        },
+        public: Some(false),
+        orientation: Some(Orientation::Portrait),
+        missing: vec![Missing::People, Missing::Date],
+        ..SearchQuery::default()
+    };
+    assert_eq!(
+        query.to_query_string(),
+        "&public=!t&orientation=portrait&missing=people&missing=date\
+         &grade_min=80",
+    );
+    assert_eq!(Orientation::parse("portrait"), Some(Orientation::Portrait));
+    assert_eq!(Missing::parse("people"), Some(Missing::People));
+    assert_eq!(Missing::parse("nothing"), None);
+}
+
+#[test]
+fn unparsed_query_is_kept() {
+    let mut query = SearchQuery::default();
+    let terms = query.parse_q("(t:a OR  t:b".into());
+    assert!(terms.is_empty());
+    assert!(query.unparsed);
+    assert!(query.error.is_some());
+    assert_eq!(query.text(), "(t:a OR t:b");
+    assert_eq!(query.to_query_string(), "&q=%28t%3Aa+OR+t%3Ab");
+}
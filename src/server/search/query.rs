@@ -0,0 +1,383 @@
+//! A compact query language for the search box.
+//!
+//! A query is a list of terms that must all match.  A term is either
+//! `kind:value`, where kind is `t` (tag), `p` (person), `l` (place),
+//! `c` (camera) or `lens`, the word `pos` for photos with a position,
+//! or any other word for free-text search.  Values with spaces can be
+//! quoted, like `p:"Kalle Anka"`.
+//!
+//! A term prefixed by `!`, `-` or `NOT` must not match.  Alternatives
+//! of the same kind are written as `t:beach|lake`, `t:beach OR t:lake`
+//! or, to negate them together, `!(t:beach OR t:lake)`.  Free-text
+//! words can also be alternatives, like `(beach OR lake)`.  `AND`
+//! between terms is allowed, but does nothing.
+
+/// A parsed search term.
+#[derive(Debug, PartialEq)]
+pub enum Term {
+    /// Photos with any of the `values` of `kind` (or none of them,
+    /// if not `inc`).
+    Facet {
+        kind: Kind,
+        inc: bool,
+        values: Vec<String>,
+    },
+    /// Photos with (or without) a position.
+    Pos(bool),
+    /// Photos with (or without) a word in their search text.
+    Word(bool, String),
+    /// Photos with any of the words (or none of them, if not `inc`).
+    Words { inc: bool, words: Vec<String> },
+}
+
+/// The kind of value in a facet term.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Tag,
+    Person,
+    Place,
+    Camera,
+    Lens,
+}
+
+/// Parse a query into terms.
+pub fn parse(query: &str) -> Result<Vec<Term>, String> {
+    let mut parser = Parser {
+        chars: query.chars().collect(),
+        pos: 0,
+    };
+    let mut result = Vec::new();
+    while parser.skip_space() {
+        if parser.peek() == Some(')') {
+            return Err("Unbalanced ')'".into());
+        }
+        if parser.eat_keyword("AND") {
+            continue;
+        }
+        result.push(parser.alternatives()?);
+    }
+    Ok(result)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    /// Skip whitespace, return true if there is more input.
+    fn skip_space(&mut self) -> bool {
+        while self.peek().map(char::is_whitespace).unwrap_or(false) {
+            self.pos += 1;
+        }
+        self.pos < self.chars.len()
+    }
+
+    /// Consume `word` if it is next in the input.
+    fn eat(&mut self, word: &str) -> bool {
+        let end = self.pos + word.chars().count();
+        let next = self.chars.get(self.pos..end);
+        if next.map(|n| n.iter().cloned().eq(word.chars())) == Some(true) {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the keyword OR, if it is next in the input.
+    fn eat_or(&mut self) -> bool {
+        self.eat_keyword("OR")
+    }
+
+    /// Consume a keyword (and space before it), if it is next in the
+    /// input as a separate word.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        if self.skip_space() && self.eat(keyword) {
+            let end = self.peek().map(is_separator).unwrap_or(true);
+            if end {
+                return true;
+            }
+        }
+        self.pos = start;
+        false
+    }
+
+    /// Consume the keyword NOT and the space after it.
+    fn eat_not(&mut self) -> bool {
+        if self.eat_keyword("NOT") {
+            self.skip_space();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// One or more terms separated by OR.
+    fn alternatives(&mut self) -> Result<Term, String> {
+        let mut term = self.term()?;
+        while self.eat_or() {
+            self.skip_space();
+            term = merge(term, self.term()?)?;
+        }
+        Ok(term)
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        let inc = !(self.eat("!") || self.eat("-") || self.eat_not());
+        if self.eat("(") {
+            self.skip_space();
+            let term = self.alternatives()?;
+            self.skip_space();
+            if !self.eat(")") {
+                return Err("Expected ')'".into());
+            }
+            return Ok(negate(term, inc));
+        }
+        let start = self.pos;
+        let mut word = self.value()?;
+        if self.peek() == Some(':') {
+            if let Some(kind) = facet_kind(&word) {
+                self.pos += 1;
+                let mut values = vec![self.value()?];
+                while self.eat("|") {
+                    values.push(self.value()?);
+                }
+                if values.iter().any(String::is_empty) {
+                    return Err(format!("Missing value for {}:", word));
+                }
+                return Ok(Term::Facet { kind, inc, values });
+            }
+        }
+        // Not a facet, so any ':' or '|' is part of the word.
+        while let Some(c) = self.peek().filter(|c| *c == ':' || *c == '|') {
+            self.pos += 1;
+            word.push(c);
+            word.push_str(&self.value()?);
+        }
+        if word.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => format!("Unexpected {:?}", c),
+                None => "Unexpected end of query".into(),
+            });
+        }
+        if word == "pos" && self.pos - start == 3 {
+            Ok(Term::Pos(inc))
+        } else {
+            Ok(Term::Word(inc, word))
+        }
+    }
+
+    /// A possibly quoted value.
+    fn value(&mut self) -> Result<String, String> {
+        let mut result = String::new();
+        while let Some(c) = self.peek() {
+            if c == '"' {
+                self.pos += 1;
+                loop {
+                    match self.peek() {
+                        Some('"') => break,
+                        Some(c) => result.push(c),
+                        None => return Err("Unterminated quote".into()),
+                    }
+                    self.pos += 1;
+                }
+            } else if is_separator(c) || c == ':' || c == '|' {
+                break;
+            } else {
+                result.push(c);
+            }
+            self.pos += 1;
+        }
+        Ok(result)
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')'
+}
+
+fn facet_kind(word: &str) -> Option<Kind> {
+    match word {
+        "t" | "tag" => Some(Kind::Tag),
+        "p" | "person" => Some(Kind::Person),
+        "l" | "place" => Some(Kind::Place),
+        "c" | "camera" => Some(Kind::Camera),
+        "lens" => Some(Kind::Lens),
+        _ => None,
+    }
+}
+
+fn negate(term: Term, inc: bool) -> Term {
+    if inc {
+        return term;
+    }
+    match term {
+        Term::Facet { kind, inc, values } => Term::Facet {
+            kind,
+            inc: !inc,
+            values,
+        },
+        Term::Pos(inc) => Term::Pos(!inc),
+        Term::Word(inc, word) => Term::Word(!inc, word),
+        Term::Words { inc, words } => Term::Words { inc: !inc, words },
+    }
+}
+
+/// Combine two alternatives into one term.
+fn merge(a: Term, b: Term) -> Result<Term, String> {
+    match (a, b) {
+        (
+            Term::Facet {
+                kind,
+                inc: true,
+                mut values,
+            },
+            Term::Facet {
+                kind: kind_b,
+                inc: true,
+                values: values_b,
+            },
+        ) if kind == kind_b => {
+            values.extend(values_b);
+            Ok(Term::Facet {
+                kind,
+                inc: true,
+                values,
+            })
+        }
+        (Term::Word(true, a), Term::Word(true, b)) => Ok(Term::Words {
+            inc: true,
+            words: vec![a, b],
+        }),
+        (
+            Term::Words {
+                inc: true,
+                mut words,
+            },
+            Term::Word(true, b),
+        ) => {
+            words.push(b);
+            Ok(Term::Words { inc: true, words })
+        }
+        _ => Err("OR is only supported between included terms of the \
+                  same kind, like t:beach OR t:lake or beach OR lake"
+            .into()),
+    }
+}
+
+#[cfg(test)]
+fn facet(kind: Kind, inc: bool, values: &[&str]) -> Term {
+    Term::Facet {
+        kind,
+        inc,
+        values: values.iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+#[test]
+fn simple_terms() {
+    assert_eq!(
+        parse("t:beach !p:kalle pos sunset"),
+        Ok(vec![
+            facet(Kind::Tag, true, &["beach"]),
+            facet(Kind::Person, false, &["kalle"]),
+            Term::Pos(true),
+            Term::Word(true, "sunset".into()),
+        ])
+    );
+}
+
+#[test]
+fn pipe_alternatives() {
+    assert_eq!(
+        parse("tag:beach|lake -tag:work"),
+        Ok(vec![
+            facet(Kind::Tag, true, &["beach", "lake"]),
+            facet(Kind::Tag, false, &["work"]),
+        ])
+    );
+}
+
+#[test]
+fn grouped_alternatives() {
+    assert_eq!(
+        parse("(t:beach OR t:lake) AND"),
+        Ok(vec![facet(Kind::Tag, true, &["beach", "lake"])])
+    );
+    assert_eq!(
+        parse("(beach OR lake) AND NOT work"),
+        Ok(vec![
+            Term::Words {
+                inc: true,
+                words: vec!["beach".into(), "lake".into()],
+            },
+            Term::Word(false, "work".into()),
+        ])
+    );
+    assert_eq!(
+        parse("NOT (sea OR lake OR pond) ANDROID"),
+        Ok(vec![
+            Term::Words {
+                inc: false,
+                words: vec!["sea".into(), "lake".into(), "pond".into()],
+            },
+            Term::Word(true, "ANDROID".into()),
+        ])
+    );
+    assert_eq!(
+        parse("!( p:a OR p:b OR p:c )"),
+        Ok(vec![facet(Kind::Person, false, &["a", "b", "c"])])
+    );
+}
+
+#[test]
+fn ungrouped_or() {
+    assert_eq!(
+        parse("l:sea OR l:lake !pos"),
+        Ok(vec![
+            facet(Kind::Place, true, &["sea", "lake"]),
+            Term::Pos(false)
+        ])
+    );
+}
+
+#[test]
+fn quoted_values() {
+    assert_eq!(
+        parse(r#"p:"Kalle Anka"|"Kajsa Anka" "pos""#),
+        Ok(vec![
+            facet(Kind::Person, true, &["Kalle Anka", "Kajsa Anka"]),
+            Term::Word(true, "pos".into()),
+        ])
+    );
+}
+
+#[test]
+fn unknown_kind_is_word() {
+    assert_eq!(
+        parse("x:y OREGON"),
+        Ok(vec![
+            Term::Word(true, "x:y".into()),
+            Term::Word(true, "OREGON".into())
+        ])
+    );
+}
+
+#[test]
+fn errors() {
+    assert!(parse("(t:a OR t:b").is_err());
+    assert!(parse("t:a)").is_err());
+    assert!(parse("t:a OR p:b").is_err());
+    assert!(parse("t:a OR !t:b").is_err());
+    assert!(parse("a OR t:b").is_err());
+    assert!(parse("NOT").is_err());
+    assert!(parse("!").is_err());
+    assert!(parse("p:\"Kalle").is_err());
+    assert!(parse("t:").is_err());
+}
@@ -1,53 +1,314 @@
-use super::views_by_date::date_of_img;
+use super::search::url_encode;
+use super::views_by_date::instant_of_img;
 use super::{Context, ImgRange, PhotoLink};
 use crate::models::{Coord, Photo};
 use crate::schema::photos;
+use diesel::dsl::sql;
 use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Integer};
 use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use warp::http::{header, Response};
 
+/// Max number of photos in one window of a listing.
+pub const PAGE_SIZE: usize = 1000;
+
+/// Get a window of photos, in the order given by `range.sort`.
+///
+/// The window starts after `range.after` (or ends before
+/// `range.before`), so a long listing is shown in pages, with links
+/// to the adjacent windows in the returned `Pager`.  Photos sorted
+/// by time are grouped, other orders are shown as they are.
 pub fn links_by_time<'a>(
     context: &Context,
     photos: photos::BoxedQuery<'a, Pg>,
     range: ImgRange,
     with_date: bool,
-) -> (Vec<PhotoLink>, Vec<(Coord, i32)>) {
+) -> (Vec<PhotoLink>, Vec<(Coord, i32)>, Pager) {
     let c = context.db().unwrap();
-    use crate::schema::photos::dsl::{date, id};
     let photos =
-        if let Some(from_date) = range.from.map(|i| date_of_img(&c, i)) {
-            photos.filter(date.ge(from_date))
+        if let Some(from_date) = range.from.map(|i| instant_of_img(&c, i)) {
+            photos.filter(Photo::instant_expr().ge(from_date))
         } else {
             photos
         };
-    let photos = if let Some(to_date) = range.to.map(|i| date_of_img(&c, i)) {
-        photos.filter(date.le(to_date))
+    let photos = if let Some(to_date) = range.to.map(|i| instant_of_img(&c, i))
+    {
+        photos.filter(Photo::instant_expr().le(to_date))
     } else {
         photos
     };
-    let photos = photos
-        .order((date.desc().nulls_last(), id.desc()))
-        .load(&c)
-        .unwrap();
-    (
-        if let Some(groups) = split_to_groups(&photos) {
-            let path = context.path_without_query();
-            groups
-                .iter()
-                .map(|g| PhotoLink::for_group(g, path, with_date))
-                .collect()
-        } else {
-            photos
-                .iter()
-                .map(if with_date {
-                    PhotoLink::date_title
-                } else {
-                    PhotoLink::no_title
-                })
-                .collect()
-        },
-        get_positions(&photos, &c),
-    )
+    // Keep a random order stable when paging.
+    let range = ImgRange {
+        seed: range.seed.or_else(|| match range.sort {
+            Sort::Random => Some(rand::random::<u16>().into()),
+            _ => None,
+        }),
+        ..range
+    };
+    let (mut photos, prev, next) = load_window(&c, photos, range);
+    if let Some(user) = context.authorized_user() {
+        if let Err(e) = Photo::apply_own_ratings(&mut photos, &c, user) {
+            warn!("Failed to load ratings of {}: {}", user, e);
+        }
+    }
+    let path = context.path_without_query();
+    let pager = Pager {
+        prev: prev.map(|id| range.href(path, "before", id)),
+        next: next.map(|id| range.href(path, "after", id)),
+        photos: photos.iter().map(Slide::from).collect(),
+        slideshow: Some(range.slideshow(path)),
+        range: Some(range),
+    };
+    let single = if with_date {
+        PhotoLink::date_title
+    } else {
+        PhotoLink::no_title
+    };
+    let links = match range.sort {
+        Sort::Newest => group_links(&photos, path, with_date, &range),
+        Sort::Oldest => {
+            // Group as newest first, to get the group ranges right.
+            let mut photos = photos.clone();
+            photos.reverse();
+            let mut links = group_links(&photos, path, with_date, &range);
+            links.reverse();
+            links
+        }
+        _ => photos.iter().map(single).collect(),
+    };
+    (links, get_positions(&photos, &c), pager)
+}
+
+/// Split photos (sorted newest first) to groups, and get a link for
+/// each group.
+fn group_links(
+    photos: &[Photo],
+    path: &str,
+    with_date: bool,
+    range: &ImgRange,
+) -> Vec<PhotoLink> {
+    if let Some(groups) = split_to_groups(photos) {
+        groups
+            .iter()
+            .map(|g| {
+                let mut link = PhotoLink::for_group(g, path, with_date);
+                if g.len() > 1 && range.sort != Sort::default() {
+                    link.href += &format!("&sort={}", range.sort.value());
+                }
+                link
+            })
+            .collect()
+    } else {
+        photos
+            .iter()
+            .map(if with_date {
+                PhotoLink::date_title
+            } else {
+                PhotoLink::no_title
+            })
+            .collect()
+    }
+}
+
+/// The order of photos in a listing.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    #[default]
+    Newest,
+    Oldest,
+    Grade,
+    Added,
+    Edited,
+    Random,
+}
+
+impl Sort {
+    pub const ALL: [Sort; 6] = [
+        Sort::Newest,
+        Sort::Oldest,
+        Sort::Grade,
+        Sort::Added,
+        Sort::Edited,
+        Sort::Random,
+    ];
+
+    /// The value of the query parameter for this order.
+    pub fn value(self) -> &'static str {
+        match self {
+            Sort::Newest => "newest",
+            Sort::Oldest => "oldest",
+            Sort::Grade => "grade",
+            Sort::Added => "added",
+            Sort::Edited => "edited",
+            Sort::Random => "random",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Sort::Newest => "Newest first",
+            Sort::Oldest => "Oldest first",
+            Sort::Grade => "Best first",
+            Sort::Added => "Recently added",
+            Sort::Edited => "Recently edited",
+            Sort::Random => "Random",
+        }
+    }
+
+    /// The sql expressions to sort by, and true for descending order.
+    ///
+    /// The expressions are never null, and ends with the unique id,
+    /// so they can be compared as a row to find the next window.
+    fn key(self, seed: u32) -> (Vec<String>, bool) {
+        let taken = |none| {
+            format!("coalesce(utc_date, date at time zone 'UTC', '{}')", none)
+        };
+        let id = "id".to_string();
+        match self {
+            Sort::Newest => (vec![taken("-infinity"), id], true),
+            Sort::Oldest => (vec![taken("infinity"), id], false),
+            Sort::Grade => (
+                vec!["coalesce(grade, -1)".into(), taken("-infinity"), id],
+                true,
+            ),
+            Sort::Added => (vec!["created".into(), id], true),
+            Sort::Edited => (vec!["updated".into(), id], true),
+            Sort::Random => {
+                (vec![format!("md5({} || '/' || id)", seed), id], false)
+            }
+        }
+    }
+}
+
+/// Load a window of photos, using the photo ids in `range.after` or
+/// `range.before` as keys.
+///
+/// Return the photos, and the keys for the previous and next
+/// windows, if any.
+fn load_window(
+    c: &PgConnection,
+    photos: photos::BoxedQuery<Pg>,
+    range: ImgRange,
+) -> (Vec<Photo>, Option<i32>, Option<i32>) {
+    let (key, desc) = range.sort.key(range.seed.unwrap_or(0));
+    let row = key.join(", ");
+    // Photos after (or before) a key photo, in the listing order.
+    let beyond = |photo: i32, after: bool| {
+        let op = if after == desc { "<" } else { ">" };
+        sql::<Bool>(&format!(
+            "({row}) {op} (select {row} from photos where id = ",
+            row = row,
+            op = op,
+        ))
+        .bind::<Integer, _>(photo)
+        .sql(")")
+    };
+    let order = |forward: bool| {
+        let dir = if forward == desc { "desc" } else { "asc" };
+        let cols = key.iter().map(|k| format!("{} {}", k, dir));
+        sql::<Integer>(&cols.collect::<Vec<_>>().join(", "))
+    };
+    if let Some(before) = range.before {
+        // Load backwards from the key, and reverse.
+        let mut photos = photos
+            .filter(beyond(before, false))
+            .order(order(false))
+            .limit(PAGE_SIZE as i64 + 1)
+            .load::<Photo>(c)
+            .unwrap();
+        let more = photos.len() > PAGE_SIZE;
+        photos.truncate(PAGE_SIZE);
+        photos.reverse();
+        let prev = photos.first().filter(|_| more).map(|p| p.id);
+        let next = photos.last().map(|p| p.id);
+        (photos, prev, next)
+    } else {
+        let photos = match range.after {
+            Some(after) => photos.filter(beyond(after, true)),
+            None => photos,
+        };
+        let mut photos = photos
+            .order(order(true))
+            .limit(PAGE_SIZE as i64 + 1)
+            .load::<Photo>(c)
+            .unwrap();
+        let more = photos.len() > PAGE_SIZE;
+        photos.truncate(PAGE_SIZE);
+        let prev = photos.first().filter(|_| range.after.is_some());
+        let next = photos.last().filter(|_| more);
+        let (prev, next) = (prev.map(|p| p.id), next.map(|p| p.id));
+        (photos, prev, next)
+    }
+}
+
+/// Links to the windows before and after the current window of a
+/// long listing.
+#[derive(Debug, Default, Serialize)]
+pub struct Pager {
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    /// The photos in the window, in order.
+    pub photos: Vec<Slide>,
+    /// A link to a slideshow of the listing.
+    #[serde(skip)]
+    pub slideshow: Option<String>,
+    /// The range of a listing that can be sorted.
+    #[serde(skip)]
+    pub range: Option<ImgRange>,
+}
+
+impl Pager {
+    pub fn is_empty(&self) -> bool {
+        self.prev.is_none() && self.next.is_none()
+    }
+
+    /// Add query parameters to the links.
+    pub fn append(&mut self, addendum: &str) {
+        for href in self.prev.iter_mut().chain(self.next.iter_mut()) {
+            href.push_str(addendum);
+        }
+        if let Some(ref mut href) = self.slideshow {
+            href.push_str(&url_encode(addendum));
+        }
+    }
+
+    /// A json response with a window of links, for infinite scroll.
+    pub fn json(&self, links: &[PhotoLink]) -> Response<Vec<u8>> {
+        #[derive(Serialize)]
+        struct Window<'a> {
+            links: &'a [PhotoLink],
+            #[serde(flatten)]
+            pager: &'a Pager,
+        }
+        let body = serde_json::to_vec(&Window { links, pager: self });
+        Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.unwrap())
+            .unwrap()
+    }
+}
+
+/// A photo as shown in a slideshow.
+#[derive(Debug, Serialize)]
+pub struct Slide {
+    pub id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl From<&Photo> for Slide {
+    fn from(photo: &Photo) -> Slide {
+        Slide {
+            id: photo.id,
+            title: photo.title.clone(),
+            description: photo.description.clone(),
+        }
+    }
 }
 
 pub fn get_positions(photos: &[Photo], c: &PgConnection) -> Vec<(Coord, i32)> {
@@ -130,7 +391,7 @@ fn split(group: &[Photo]) -> (&[Photo], &[Photo]) {
 }
 
 fn timestamp(p: &Photo) -> i64 {
-    p.date.map(|d| d.timestamp()).unwrap_or(0)
+    p.timestamp().unwrap_or(0)
 }
 
 #[test]
@@ -169,6 +430,39 @@ fn split_group_same_time() {
     assert_eq!(paths(split(&photos)), paths((&photos[..2], &photos[2..])));
 }
 
+#[test]
+fn pager_links() {
+    let range = ImgRange {
+        from: Some(17),
+        ..ImgRange::default()
+    };
+    let mut pager = Pager {
+        prev: None,
+        next: Some(range.href("/tag/x", "after", 42)),
+        photos: vec![],
+        slideshow: Some(range.slideshow("/tag/x")),
+        range: Some(range),
+    };
+    pager.append("&t=y");
+    assert_eq!(pager.next.as_deref(), Some("/tag/x?from=17&after=42&t=y"));
+    assert_eq!(
+        pager.slideshow.as_deref(),
+        Some("/slideshow?of=%2Ftag%2Fx%3Ffrom%3D17%26t%3Dy"),
+    );
+    assert!(!pager.is_empty());
+}
+
+#[test]
+fn sorted_range_links() {
+    let range: ImgRange =
+        serde_urlencoded::from_str("sort=random&seed=7&after=3").unwrap();
+    assert_eq!(range.sort, Sort::Random);
+    assert_eq!(range.after, Some(3));
+    assert_eq!(range.href("/", "after", 9), "/?sort=random&seed=7&after=9");
+    let range = ImgRange::default();
+    assert_eq!(range.href("/", "before", 9), "/?before=9");
+}
+
 #[cfg(test)]
 fn paths<'a>(
     (a, b): (&'a [Photo], &'a [Photo]),
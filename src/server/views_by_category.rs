@@ -1,6 +1,6 @@
 //! Handle photos by tag, person, or place.
 use super::render_ructe::RenderRucte;
-use super::{links_by_time, not_found, Context, ImgRange};
+use super::{links_by_time, not_found, Context, ImgRange, Link};
 use crate::models::{Person, Photo, Place, Tag};
 use crate::templates;
 use diesel::prelude::*;
@@ -20,14 +20,15 @@ pub fn person_all(context: Context) -> Response<Vec<u8>> {
             pp::photo_id.eq_any(p::photos.select(p::id).filter(p::is_public)),
         )))
     };
+    let db = context.db().unwrap();
+    let portraits = Person::with_portraits(&db, context.is_authorized())
+        .expect("find portraits");
     Response::builder().html(|o| {
         templates::people(
             o,
             &context,
-            &query
-                .order(person_name)
-                .load(&context.db().unwrap())
-                .expect("list people"),
+            &query.order(person_name).load(&db).expect("list people"),
+            &portraits,
         )
     })
 }
@@ -51,9 +52,18 @@ pub fn person_one(
                     .filter(person_id.eq(person.id)),
             ),
         );
-        let (links, coords) = links_by_time(&context, photos, range, true);
-        Response::builder()
-            .html(|o| templates::person(o, &context, &links, &coords, &person))
+        let (links, coords, pager) =
+            links_by_time(&context, photos, range, true);
+        if range.json {
+            return pager.json(&links);
+        }
+        let portrait =
+            person.portrait(&c, context.is_authorized()).unwrap_or(None);
+        Response::builder().html(|o| {
+            templates::person(
+                o, &context, &links, &coords, &pager, &person, &portrait,
+            )
+        })
     } else {
         not_found(&context)
     }
@@ -95,36 +105,58 @@ pub fn tag_one(
         let photos = Photo::query(context.is_authorized()).filter(
             id.eq_any(photo_tags.select(photo_id).filter(tag_id.eq(tag.id))),
         );
-        let (links, coords) = links_by_time(&context, photos, range, true);
-        Response::builder()
-            .html(|o| templates::tag(o, &context, &links, &coords, &tag))
+        let (links, coords, pager) =
+            links_by_time(&context, photos, range, true);
+        if range.json {
+            return pager.json(&links);
+        }
+        Response::builder().html(|o| {
+            templates::tag(o, &context, &links, &coords, &pager, &tag)
+        })
     } else {
         not_found(&context)
     }
 }
 
 pub fn place_all(context: Context) -> Response<Vec<u8>> {
-    use crate::schema::places::dsl::{id, place_name, places};
-    let query = places.into_boxed();
-    let query = if context.is_authorized() {
-        query
+    let places = places_with_count(&context, None);
+    Response::builder().html(|o| templates::places(o, &context, &places))
+}
+
+/// Places with a given parent (or top-level places), with the number
+/// of photos in each.
+///
+/// Places without any (visible) photos are excluded.
+fn places_with_count(
+    context: &Context,
+    parent: Option<i32>,
+) -> Vec<(Place, i64)> {
+    use crate::schema::places::dsl as l;
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+    let count = sql::<BigInt>(if context.is_authorized() {
+        "(select count(*) from photo_places pp \
+          where pp.place_id = places.id)"
     } else {
-        use crate::schema::photo_places::dsl as pp;
-        use crate::schema::photos::dsl as p;
-        query.filter(id.eq_any(pp::photo_places.select(pp::place_id).filter(
-            pp::photo_id.eq_any(p::photos.select(p::id).filter(p::is_public)),
-        )))
+        "(select count(*) from photo_places pp \
+          join photos p on p.id = pp.photo_id \
+          where pp.place_id = places.id and p.is_public)"
+    });
+    let query = l::places
+        .select((crate::schema::places::all_columns, count))
+        .into_boxed();
+    let query = if let Some(parent) = parent {
+        query.filter(l::parent_id.eq(parent))
+    } else {
+        query.filter(l::parent_id.is_null())
     };
-    Response::builder().html(|o| {
-        templates::places(
-            o,
-            &context,
-            &query
-                .order(place_name)
-                .load(&context.db().unwrap())
-                .expect("List places"),
-        )
-    })
+    query
+        .order(l::place_name)
+        .load::<(Place, i64)>(&context.db().unwrap())
+        .expect("List places")
+        .into_iter()
+        .filter(|(_, n)| *n > 0)
+        .collect()
 }
 
 pub fn place_one(
@@ -133,10 +165,8 @@ pub fn place_one(
     range: ImgRange,
 ) -> Response<Vec<u8>> {
     use crate::schema::places::dsl::{places, slug};
-    if let Ok(place) = places
-        .filter(slug.eq(tslug))
-        .first::<Place>(&context.db().unwrap())
-    {
+    let db = context.db().unwrap();
+    if let Ok(place) = places.filter(slug.eq(tslug)).first::<Place>(&db) {
         use crate::schema::photo_places::dsl::{
             photo_id, photo_places, place_id,
         };
@@ -144,9 +174,23 @@ pub fn place_one(
         let photos = Photo::query(context.is_authorized()).filter(id.eq_any(
             photo_places.select(photo_id).filter(place_id.eq(place.id)),
         ));
-        let (links, coord) = links_by_time(&context, photos, range, true);
-        Response::builder()
-            .html(|o| templates::place(o, &context, &links, &coord, &place))
+        let (links, coord, pager) =
+            links_by_time(&context, photos, range, true);
+        if range.json {
+            return pager.json(&links);
+        }
+        let lpath = place
+            .load_ancestors(&db)
+            .unwrap()
+            .iter()
+            .map(Link::place)
+            .collect::<Vec<_>>();
+        let children = places_with_count(&context, Some(place.id));
+        Response::builder().html(|o| {
+            templates::place(
+                o, &context, &lpath, &links, &coord, &pager, &place, &children,
+            )
+        })
     } else {
         not_found(&context)
     }
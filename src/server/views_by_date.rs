@@ -1,10 +1,10 @@
 use super::render_ructe::RenderRucte;
-use super::splitlist::links_by_time;
+use super::splitlist::{links_by_time, Pager, Slide, PAGE_SIZE};
 use super::{not_found, redirect_to_img, Context, ImgRange, Link, PhotoLink};
-use crate::models::{Photo, SizeTag};
+use crate::models::{Photo, SavedSearch, SizeTag};
 use crate::templates;
 use chrono::naive::{NaiveDate, NaiveDateTime};
-use chrono::{Datelike, Duration, Local};
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
 use diesel::dsl::sql;
 use diesel::prelude::*;
 use diesel::sql_types::{BigInt, Integer, Nullable};
@@ -49,9 +49,13 @@ pub fn all_years(context: Context) -> impl Reply {
         })
         .collect::<Vec<_>>();
 
-    Response::builder().html(|o| {
-        templates::index(o, &context, "All photos", &[], &groups, &[])
-    })
+    let saved = if context.is_authorized() {
+        SavedSearch::list(&db, true).unwrap()
+    } else {
+        vec![]
+    };
+    Response::builder()
+        .html(|o| templates::frontpage(o, &context, &groups, &saved))
 }
 
 fn start_of_year(year: i32) -> NaiveDateTime {
@@ -114,7 +118,15 @@ pub fn months_in_year(year: i32, context: Context) -> Response<Vec<u8>> {
             })
             .collect::<Vec<_>>();
         Response::builder().html(|o| {
-            templates::index(o, &context, &title, &[], &groups, &pos)
+            templates::index(
+                o,
+                &context,
+                &title,
+                &[],
+                &groups,
+                &pos,
+                &Pager::default(),
+            )
         })
     }
 }
@@ -191,30 +203,75 @@ pub fn days_in_month(
             })
             .collect::<Vec<_>>();
         Response::builder().html(|o| {
-            templates::index(o, &context, &title, &lpath, &groups, &pos)
+            templates::index(
+                o,
+                &context,
+                &title,
+                &lpath,
+                &groups,
+                &pos,
+                &Pager::default(),
+            )
         })
     }
 }
 
-pub fn all_null_date(context: Context) -> impl Reply {
-    use crate::schema::photos::dsl::{date, path};
-
+/// Photos without a date, by path, in windows of `PAGE_SIZE`.
+pub fn all_null_date(context: Context, range: ImgRange) -> impl Reply {
+    use crate::schema::photos::dsl::{date, path, photos};
+    let db = context.db().unwrap();
+    let path_of = |photo: Option<i32>| {
+        photos.find(photo?).select(path).first::<String>(&db).ok()
+    };
+    let query = Photo::query(context.is_authorized())
+        .filter(date.is_null())
+        .limit(PAGE_SIZE as i64 + 1);
+    let (found, prev, next) = if let Some(key) = path_of(range.before) {
+        let mut found = query
+            .filter(path.lt(key))
+            .order(path.desc())
+            .load::<Photo>(&db)
+            .unwrap();
+        let more = found.len() > PAGE_SIZE;
+        found.truncate(PAGE_SIZE);
+        found.reverse();
+        let prev = found.first().filter(|_| more).map(|p| p.id);
+        let next = found.last().map(|p| p.id);
+        (found, prev, next)
+    } else {
+        let query = match path_of(range.after) {
+            Some(key) => query.filter(path.gt(key)),
+            None => query,
+        };
+        let mut found = query.order(path.asc()).load::<Photo>(&db).unwrap();
+        let more = found.len() > PAGE_SIZE;
+        found.truncate(PAGE_SIZE);
+        let prev = found.first().filter(|_| range.after.is_some());
+        let next = found.last().filter(|_| more);
+        let (prev, next) = (prev.map(|p| p.id), next.map(|p| p.id));
+        (found, prev, next)
+    };
+    let base = context.path_without_query();
+    let pager = Pager {
+        prev: prev.map(|id| format!("{}?before={}", base, id)),
+        next: next.map(|id| format!("{}?after={}", base, id)),
+        photos: found.iter().map(Slide::from).collect(),
+        slideshow: None,
+        range: None,
+    };
+    let links = found.iter().map(PhotoLink::no_title).collect::<Vec<_>>();
+    if range.json {
+        return pager.json(&links);
+    }
     Response::builder().html(|o| {
         templates::index(
             o,
             &context,
             "Photos without a date",
             &[],
-            &Photo::query(context.is_authorized())
-                .filter(date.is_null())
-                .order(path.asc())
-                .limit(500)
-                .load(&context.db().unwrap())
-                .unwrap()
-                .iter()
-                .map(PhotoLink::no_title)
-                .collect::<Vec<_>>(),
+            &links,
             &[], // Don't care about positions here
+            &pager,
         )
     })
 }
@@ -232,9 +289,11 @@ pub fn all_for_day(
     let photos = Photo::query(context.is_authorized())
         .filter(date.ge(thedate))
         .filter(date.lt(thedate + Duration::days(1)));
-    let (links, coords) = links_by_time(&context, photos, range, false);
+    let (links, coords, pager) = links_by_time(&context, photos, range, false);
 
-    if links.is_empty() {
+    if range.json {
+        pager.json(&links)
+    } else if links.is_empty() {
         not_found(&context)
     } else {
         Response::builder().html(|o| {
@@ -245,6 +304,7 @@ pub fn all_for_day(
                 &[Link::year(year), Link::month(year, month)],
                 &links,
                 &coords,
+                &pager,
             )
         })
     }
@@ -320,21 +380,21 @@ pub fn on_this_day(context: Context) -> impl Reply {
                 })
                 .collect::<Vec<_>>(),
             &pos,
+            &Pager::default(),
         )
     })
 }
 
 pub fn next_image(context: Context, param: FromParam) -> impl Reply {
-    use crate::schema::photos::dsl::{date, id};
+    use crate::schema::photos::dsl::id;
     let db = context.db().unwrap();
-    if let Some(from_date) = date_of_img(&db, param.from) {
+    if let Some(from_date) = instant_of_img(&db, param.from) {
         let q = Photo::query(context.is_authorized())
             .select(id)
-            .filter(
-                date.gt(from_date)
-                    .or(date.eq(from_date).and(id.gt(param.from))),
-            )
-            .order((date, id));
+            .filter(Photo::instant_expr().gt(from_date).or(
+                Photo::instant_expr().eq(from_date).and(id.gt(param.from)),
+            ))
+            .order((Photo::instant_expr(), id));
         if let Ok(photo) = q.first::<i32>(&db) {
             return redirect_to_img(photo);
         }
@@ -343,16 +403,15 @@ pub fn next_image(context: Context, param: FromParam) -> impl Reply {
 }
 
 pub fn prev_image(context: Context, param: FromParam) -> impl Reply {
-    use crate::schema::photos::dsl::{date, id};
+    use crate::schema::photos::dsl::id;
     let db = context.db().unwrap();
-    if let Some(from_date) = date_of_img(&db, param.from) {
+    if let Some(from_date) = instant_of_img(&db, param.from) {
         let q = Photo::query(context.is_authorized())
             .select(id)
-            .filter(
-                date.lt(from_date)
-                    .or(date.eq(from_date).and(id.lt(param.from))),
-            )
-            .order((date.desc().nulls_last(), id.desc()));
+            .filter(Photo::instant_expr().lt(from_date).or(
+                Photo::instant_expr().eq(from_date).and(id.lt(param.from)),
+            ))
+            .order((Photo::instant_expr().desc().nulls_last(), id.desc()));
         if let Ok(photo) = q.first::<i32>(&db) {
             return redirect_to_img(photo);
         }
@@ -365,9 +424,17 @@ pub struct FromParam {
     from: i32,
 }
 
-pub fn date_of_img(db: &PgConnection, photo_id: i32) -> Option<NaiveDateTime> {
-    use crate::schema::photos::dsl::{date, photos};
-    photos.find(photo_id).select(date).first(db).unwrap_or(None)
+/// The instant a photo was taken, see `Photo::instant_expr`.
+pub fn instant_of_img(
+    db: &PgConnection,
+    photo_id: i32,
+) -> Option<DateTime<Utc>> {
+    use crate::schema::photos::dsl::photos;
+    photos
+        .find(photo_id)
+        .select(Photo::instant_expr())
+        .first(db)
+        .unwrap_or(None)
 }
 
 pub fn monthname(n: u32) -> &'static str {
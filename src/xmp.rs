@@ -0,0 +1,208 @@
+//! Read and write face regions in xmp, as described by the Metadata
+//! Working Group (MWG-RS).
+use crate::myexif::jpeg_segment;
+use log::warn;
+use roxmltree::Node;
+use std::fmt::Write;
+use std::path::Path;
+
+const XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const MWG_RS: &str = "http://www.metadataworkinggroup.com/schemas/regions/";
+const ST_AREA: &str = "http://ns.adobe.com/xmp/sType/Area#";
+const ST_DIM: &str = "http://ns.adobe.com/xap/1.0/sType/Dimensions#";
+
+/// A named face region.
+///
+/// The center and size of the area are fractions of the width and
+/// height of the image as stored, before rotation.
+#[derive(Debug, PartialEq)]
+pub struct Face {
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+/// Read the face regions from the xmp data embedded in a jpeg file.
+pub fn read_faces(path: &Path) -> Vec<Face> {
+    let xmp = match jpeg_segment(path, 0xE1, XMP_PREFIX) {
+        Ok(Some(xmp)) => xmp,
+        Ok(None) => return vec![],
+        Err(e) => {
+            warn!("Failed to read xmp from {}: {}", path.display(), e);
+            return vec![];
+        }
+    };
+    String::from_utf8(xmp)
+        .map_err(|e| e.to_string())
+        .and_then(|xmp| parse_faces(&xmp))
+        .unwrap_or_else(|e| {
+            warn!("Bad xmp in {}: {}", path.display(), e);
+            vec![]
+        })
+}
+
+/// Get the named face regions of an xmp document.
+///
+/// Regions of other types than face, and regions without a name,
+/// are ignored.
+pub fn parse_faces(xmp: &str) -> Result<Vec<Face>, String> {
+    let doc = roxmltree::Document::parse(xmp).map_err(|e| e.to_string())?;
+    let mut result = vec![];
+    for list in doc
+        .descendants()
+        .filter(|n| n.has_tag_name((MWG_RS, "RegionList")))
+    {
+        for li in list.descendants().filter(|n| n.has_tag_name((RDF, "li"))) {
+            let region = description(li);
+            if property(region, MWG_RS, "Type").is_some_and(|t| t != "Face") {
+                continue;
+            }
+            let area = match region
+                .children()
+                .find(|n| n.has_tag_name((MWG_RS, "Area")))
+            {
+                Some(area) => description(area),
+                None => continue,
+            };
+            if property(area, ST_AREA, "unit")
+                .is_some_and(|u| u != "normalized")
+            {
+                continue;
+            }
+            let value = |name| {
+                property(area, ST_AREA, name)
+                    .and_then(|v| v.trim().parse().ok())
+            };
+            if let (Some(name), Some(x), Some(y), Some(w), Some(h)) = (
+                property(region, MWG_RS, "Name"),
+                value("x"),
+                value("y"),
+                value("w"),
+                value("h"),
+            ) {
+                result.push(Face { name, x, y, w, h });
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// An xmp document with the face regions of an image.
+///
+/// The width and height are the size of the image as stored.
+pub fn faces_xmp(faces: &[Face], width: i32, height: i32) -> String {
+    let mut list = String::new();
+    for face in faces {
+        let _ = write!(
+            list,
+            "     <rdf:li><rdf:Description mwg-rs:Name=\"{}\" \
+             mwg-rs:Type=\"Face\">\n      \
+             <mwg-rs:Area stArea:x=\"{:.5}\" stArea:y=\"{:.5}\" \
+             stArea:w=\"{:.5}\" stArea:h=\"{:.5}\" \
+             stArea:unit=\"normalized\"/>\n     \
+             </rdf:Description></rdf:li>\n",
+            escape(&face.name),
+            face.x,
+            face.y,
+            face.w,
+            face.h,
+        );
+    }
+    format!(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+         <rdf:RDF xmlns:rdf=\"{}\">\n  \
+         <rdf:Description rdf:about=\"\" xmlns:mwg-rs=\"{}\" \
+         xmlns:stArea=\"{}\" xmlns:stDim=\"{}\">\n   \
+         <mwg-rs:Regions rdf:parseType=\"Resource\">\n    \
+         <mwg-rs:AppliedToDimensions stDim:w=\"{}\" stDim:h=\"{}\" \
+         stDim:unit=\"pixel\"/>\n    \
+         <mwg-rs:RegionList><rdf:Bag>\n{}    \
+         </rdf:Bag></mwg-rs:RegionList>\n   \
+         </mwg-rs:Regions>\n  \
+         </rdf:Description>\n \
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n",
+        RDF, MWG_RS, ST_AREA, ST_DIM, width, height, list,
+    )
+}
+
+/// A struct in rdf is either the node itself (with parseType
+/// Resource) or an rdf:Description inside it.
+fn description<'a, 'i>(node: Node<'a, 'i>) -> Node<'a, 'i> {
+    node.children()
+        .find(|n| n.has_tag_name((RDF, "Description")))
+        .unwrap_or(node)
+}
+
+/// A simple property, either as an attribute or as a child element.
+fn property(node: Node, ns: &str, name: &str) -> Option<String> {
+    node.attribute((ns, name))
+        .or_else(|| {
+            node.children()
+                .find(|n| n.has_tag_name((ns, name)))
+                .and_then(|n| n.text())
+        })
+        .map(String::from)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn parse_elements_and_attributes() {
+    let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:mwg-rs="http://www.metadataworkinggroup.com/schemas/regions/"
+    xmlns:stArea="http://ns.adobe.com/xmp/sType/Area#">
+   <mwg-rs:Regions rdf:parseType="Resource">
+    <mwg-rs:RegionList>
+     <rdf:Bag>
+      <rdf:li rdf:parseType="Resource">
+       <mwg-rs:Name>Kalle Anka</mwg-rs:Name>
+       <mwg-rs:Type>Face</mwg-rs:Type>
+       <mwg-rs:Area stArea:x="0.5" stArea:y="0.25" stArea:w="0.1"
+         stArea:h="0.2" stArea:unit="normalized"/>
+      </rdf:li>
+      <rdf:li>
+       <rdf:Description mwg-rs:Name="Ball" mwg-rs:Type="Pet">
+        <mwg-rs:Area stArea:x="0.1" stArea:y="0.1" stArea:w="0.1"
+          stArea:h="0.1" stArea:unit="normalized"/>
+       </rdf:Description>
+      </rdf:li>
+     </rdf:Bag>
+    </mwg-rs:RegionList>
+   </mwg-rs:Regions>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+    assert_eq!(
+        parse_faces(xmp),
+        Ok(vec![Face {
+            name: "Kalle Anka".into(),
+            x: 0.5,
+            y: 0.25,
+            w: 0.1,
+            h: 0.2,
+        }]),
+    );
+}
+
+#[test]
+fn write_and_read_faces() {
+    let faces = vec![Face {
+        name: "Knatte & <Fnatte>".into(),
+        x: 0.25,
+        y: 0.5,
+        w: 0.125,
+        h: 0.25,
+    }];
+    assert_eq!(parse_faces(&faces_xmp(&faces, 4000, 3000)), Ok(faces));
+}